@@ -1,5 +1,6 @@
 use std::{error::Error, fs::File, io::Read, path::Path};
 
+use medup::markdown::{HtmlOptions, SanitizeOptions, SoftBreak, TocOptions};
 use serde::{Deserialize, Serialize};
 
 const TEMPLATE_FILE_NAME: &str = "template.txt";
@@ -10,6 +11,16 @@ pub(crate) struct ConfigJson {
     pub body_max_width: i32,
     pub use_slice_mode: bool,
     pub slice_header: String,
+    // The theme of the server-side syntax highlighting, it's disabled if not specified
+    pub highlight_theme: Option<String>,
+    // The allowlist to sanitize the raw html, it's disabled if not specified
+    pub sanitize: Option<SanitizeOptions>,
+    // Disable the safe mode, the urls of the links and images are output without any check
+    #[serde(default)]
+    pub unsafe_mode: bool,
+    // The rendering of the soft line breaks, "newline", "br" (default) or "cjk"
+    #[serde(default)]
+    pub soft_break: SoftBreak,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -35,6 +46,16 @@ impl Config {
         &self.config_json.slice_header
     }
 
+    pub(crate) fn html_options(&self) -> HtmlOptions {
+        HtmlOptions {
+            highlight_theme: self.config_json.highlight_theme.clone(),
+            sanitize: self.config_json.sanitize.clone(),
+            safe: !self.config_json.unsafe_mode,
+            soft_break: self.config_json.soft_break,
+            toc: TocOptions::default(),
+        }
+    }
+
     pub(crate) fn template(&self) -> &str {
         &self.template
     }
@@ -53,13 +74,13 @@ impl Config {
 
         // read template
         let mut buf = String::new();
-        Path::new(path).parent().map(|p| {
+        if let Some(p) = Path::new(path).parent() {
             let mut template_path = p.to_path_buf();
             template_path.push(TEMPLATE_FILE_NAME);
             File::open(template_path)
                 .map(|mut f| f.read_to_string(&mut buf))
                 .ok();
-        });
+        }
 
         Ok(Config {
             config_json: cj,
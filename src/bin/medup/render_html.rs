@@ -1,15 +1,23 @@
 use std::error::Error;
 
 use serde::Serialize;
+use serde_json::Value;
 use tinytemplate::TinyTemplate;
+use v_htmlescape as htmlescape;
 
 use crate::config::Config;
+use medup::Metadata;
 
 const TPL_HTML_NAME: &str = "template";
 
+const DEFAULT_TITLE: &str = "medup";
+
 #[derive(Serialize)]
 struct HtmlContext<'html_context> {
     title: &'html_context str,
+    meta: &'html_context Metadata,
+    // the template can load the math script only if the document contains math
+    has_math: bool,
     body_min_width: i32,
     body_max_width: i32,
     use_slice_mode: bool,
@@ -30,19 +38,38 @@ impl<'render_html> RenderHtml<'render_html> {
         Ok(RenderHtml { tt })
     }
 
-    pub(crate) fn exec(&self, cfg: &Config, data: &Vec<String>) -> Result<String, Box<dyn Error>> {
+    pub(crate) fn exec(
+        &self,
+        cfg: &Config,
+        meta: &Metadata,
+        has_math: bool,
+        data: &Vec<String>,
+    ) -> Result<String, Box<dyn Error>> {
         let content = if !cfg.use_slice_mode() {
             data.join("")
         } else {
             "".to_string()
         };
 
+        // the values of the front matter are inserted into the template as they are, so they're
+        // escaped in advance, the title of the document comes from the front matter as well
+        let meta: Metadata = meta
+            .iter()
+            .map(|(k, v)| (k.to_string(), escape_value(v)))
+            .collect();
+        let title = meta
+            .get("title")
+            .and_then(|v| v.as_str())
+            .unwrap_or(DEFAULT_TITLE);
+
         let ctx = HtmlContext {
-            title: "medup",
+            title,
+            meta: &meta,
+            has_math,
             body_min_width: cfg.body_min_width(),
             body_max_width: cfg.body_max_width(),
             use_slice_mode: cfg.use_slice_mode(),
-            slice_header: &cfg.slice_header(),
+            slice_header: cfg.slice_header(),
             content: &content,
             slices: data,
         };
@@ -51,3 +78,17 @@ impl<'render_html> RenderHtml<'render_html> {
         Ok(s)
     }
 }
+
+// Escape the strings in the value of the front matter recursively
+fn escape_value(v: &Value) -> Value {
+    match v {
+        Value::String(s) => Value::String(htmlescape::escape(s).to_string()),
+        Value::Array(a) => Value::Array(a.iter().map(escape_value).collect()),
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .map(|(k, v)| (k.to_string(), escape_value(v)))
+                .collect(),
+        ),
+        _ => v.clone(),
+    }
+}
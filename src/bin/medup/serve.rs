@@ -67,18 +67,20 @@ fn articles_filter(cfg: Config, dir: String) -> BoxedFilter<(impl Reply,)> {
                         format!(r#"failed to join the path: {}, {}"#, dir, name),
                     ),
                     Some(path) => {
+                        let opts = cfg.html_options();
                         let func = if cfg.use_slice_mode() {
-                            markdown::to_slice
+                            markdown::to_slice_with
                         } else {
-                            markdown::to_body
+                            markdown::to_body_with
                         };
 
-                        match Markdown::new().path(path).map_mut(func) {
+                        let mut md = Markdown::new();
+                        match md.path(path).map_mut(|ast| func(ast, &opts)) {
                             Err(e) => error_repsonse(
                                 StatusCode::INTERNAL_SERVER_ERROR,
                                 format!("failed to generate body from markdown: {}", e),
                             ),
-                            Ok(v) => match render.exec(&cfg, &v) {
+                            Ok(v) => match render.exec(&cfg, md.metadata(), md.has_math(), &v) {
                                 Err(e) => error_repsonse(
                                     StatusCode::INTERNAL_SERVER_ERROR,
                                     format!("failed to render html: {}", e),
@@ -111,19 +113,26 @@ fn index_filter(cfg: Config, dir: String) -> BoxedFilter<(impl Reply,)> {
                         StatusCode::BAD_REQUEST,
                         format!(r#"failed to join the path: {}, index.md"#, dir),
                     ),
-                    Some(path) => match Markdown::new().path(path).map_mut(markdown::to_body) {
-                        Err(e) => error_repsonse(
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("failed to generate body from markdown: {}", e),
-                        ),
-                        Ok(v) => match render.exec(&cfg, &v) {
+                    Some(path) => {
+                        let opts = cfg.html_options();
+                        let mut md = Markdown::new();
+                        match md
+                            .path(path)
+                            .map_mut(|ast| markdown::to_body_with(ast, &opts))
+                        {
                             Err(e) => error_repsonse(
                                 StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("failed to render html: {}", e),
+                                format!("failed to generate body from markdown: {}", e),
                             ),
-                            Ok(s) => warp::reply::html(s).into_response(),
-                        },
-                    },
+                            Ok(v) => match render.exec(&cfg, md.metadata(), md.has_math(), &v) {
+                                Err(e) => error_repsonse(
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    format!("failed to render html: {}", e),
+                                ),
+                                Ok(s) => warp::reply::html(s).into_response(),
+                            },
+                        }
+                    }
                 },
             },
         )
@@ -142,8 +151,9 @@ fn load_config(matches: &ArgMatches) -> Result<Config, Box<dyn Error>> {
     // read config path from cli
     let cfg = match matches.get_one::<String>("config-path") {
         None => Config::default(),
-        Some(path) => Config::read(path)
-            .map_err(|e| (format!("failed to read config \"{}\": {}", path, e)))?,
+        Some(path) => {
+            Config::read(path).map_err(|e| format!("failed to read config \"{}\": {}", path, e))?
+        }
     };
     Ok(cfg)
 }
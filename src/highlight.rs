@@ -0,0 +1,104 @@
+use std::error::Error;
+
+use lazy_static::lazy_static;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::SyntaxSet;
+
+// Loading the syntaxes and themes is expensive, so they are loaded only once
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+}
+
+// Highlighter colors the code on the server side, the colors are inlined into the html as styles,
+// so it works without any javascript and css.
+pub(crate) struct Highlighter {
+    theme: &'static Theme,
+}
+
+impl Highlighter {
+    // Create a highlighter with the theme name, .e.g "InspiredGitHub", "base16-ocean.dark"
+    pub(crate) fn new(theme: &str) -> Result<Self, Box<dyn Error>> {
+        match THEME_SET.themes.get(theme) {
+            Some(theme) => Ok(Highlighter { theme }),
+            None => Err(format!(
+                "unknown highlight theme \"{}\", the available themes are: {}",
+                theme,
+                Self::themes().join(", ")
+            )
+            .into()),
+        }
+    }
+
+    // Get the names of all available themes
+    pub(crate) fn themes() -> Vec<&'static str> {
+        THEME_SET.themes.keys().map(|k| k.as_str()).collect()
+    }
+
+    // Highlight the lines of code by the language, the language is the name or file extension
+    // of the syntax, .e.g "rust", "rs".
+    // Every line must end with a newline('\n'), the result is html escaped and ends with the newline.
+    // Return None if the language is not supported.
+    pub(crate) fn highlight(&self, lang: &str, lines: &[&str]) -> Option<Vec<String>> {
+        let syntax = SYNTAX_SET.find_syntax_by_token(lang)?;
+        let mut h = HighlightLines::new(syntax, self.theme);
+
+        lines
+            .iter()
+            .map(|l| {
+                let ranges = h.highlight_line(l, &SYNTAX_SET).ok()?;
+                let html = styled_line_to_highlighted_html(&ranges, IncludeBackground::No).ok()?;
+                // move the newline out of the last span
+                Some(format!("{}\n", html.replace('\n', "")))
+            })
+            .collect()
+    }
+
+    // Get the style of the code block container, it's the foreground and background of the theme
+    pub(crate) fn block_style(&self) -> String {
+        let mut style = String::new();
+        if let Some(c) = self.theme.settings.foreground {
+            style.push_str(&format!("color: #{:02x}{:02x}{:02x};", c.r, c.g, c.b));
+        }
+        if let Some(c) = self.theme.settings.background {
+            style.push_str(&format!(
+                "background-color: #{:02x}{:02x}{:02x};",
+                c.r, c.g, c.b
+            ));
+        }
+        style
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let h = Highlighter::new("InspiredGitHub").unwrap();
+
+        let lines = h.highlight("rs", &["let a = \"<b>\";\n", "\n"]).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("<span style=\""));
+        assert!(lines[0].contains("&lt;b&gt;"));
+        assert!(!lines[0].contains("<b>"));
+        assert!(lines
+            .iter()
+            .all(|l| l.ends_with('\n') && l.matches('\n').count() == 1));
+
+        // the language is not supported
+        assert_eq!(h.highlight("no-such-lang", &["x\n"]), None);
+
+        assert!(h.block_style().contains("background-color: #"));
+    }
+
+    #[test]
+    fn test_unknown_theme() {
+        let err = Highlighter::new("no-such-theme").err().unwrap().to_string();
+        assert!(err.contains("unknown highlight theme \"no-such-theme\""));
+        assert!(err.contains("InspiredGitHub"));
+    }
+}
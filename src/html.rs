@@ -1,27 +1,82 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
 
-use crate::lexer::{Token, TokenKind};
-use crate::utils::stack;
+use crate::highlight::Highlighter;
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::markdown::{HtmlOptions, SanitizeOptions, SoftBreak};
+use crate::parser::Line;
+use crate::utils::{self, stack};
 use crate::Generate;
 use crate::SharedLine;
 
+use lazy_static::lazy_static;
 use serde::Serialize;
 use tinytemplate::TinyTemplate;
 use v_htmlescape as htmlescape;
 
+// The raw html is sanitized by the default allowlist in the safe mode if no policy is specified
+lazy_static! {
+    static ref DEFAULT_SANITIZE: SanitizeOptions = SanitizeOptions::default();
+}
+
 pub(crate) struct Generator<'generator> {
     template: TinyTemplate<'generator>,
     ref_link_tags: &'generator HashMap<String, (String, String)>,
+    footnotes: &'generator HashMap<String, String>,
+    // The labels of the referenced footnotes, the index plus 1 is the number of the footnote
+    footnote_refs: RefCell<Vec<String>>,
+    // The footnote numbers referenced since the footnotes were rendered last time
+    footnote_pending: RefCell<Vec<usize>>,
+    // The code blocks are highlighted on the server side if it's not None
+    highlighter: Option<Highlighter>,
+    // The raw html is sanitized if it's not None
+    sanitizer: Option<ammonia::Builder<'generator>>,
+    // The generic attributes of the specified sanitizer, the attributes in the braces are kept
+    // only if they're in the list, .e.g "{#id .class}"
+    generic_attributes: Option<&'generator [String]>,
+    // The raw html blocks to be sanitized with the whole contents, they're marked in the contents
+    // by the 'RAW_HTML_MARK'
+    raw_blocks: RefCell<Vec<String>>,
+    // The links and images with the unsafe urls are not output in the safe mode
+    safe: bool,
+    // The rendering of the soft line breaks in the paragraph
+    soft_break: SoftBreak,
 }
 
 impl<'generator> Generator<'generator> {
     pub(crate) fn new(
         ref_link_tags: &'generator HashMap<String, (String, String)>,
+        footnotes: &'generator HashMap<String, String>,
+        opts: &'generator HtmlOptions,
     ) -> Result<Self, Box<dyn Error>> {
+        let highlighter = match opts.highlight_theme.as_deref() {
+            Some(theme) => Some(Highlighter::new(theme)?),
+            None => None,
+        };
+        let sanitize = match opts.sanitize.as_ref() {
+            None if opts.safe => Some(&*DEFAULT_SANITIZE),
+            so => so,
+        };
         let mut g = Generator {
             template: TinyTemplate::new(),
             ref_link_tags,
+            footnotes,
+            footnote_refs: RefCell::new(vec![]),
+            footnote_pending: RefCell::new(vec![]),
+            highlighter,
+            sanitizer: sanitize.map(|so| {
+                let mut b = so.builder();
+                if opts.safe {
+                    // the urls in the raw html have the same schemes as the links and images
+                    b.url_schemes(utils::SAFE_URL_SCHEMES.into_iter().collect());
+                }
+                b
+            }),
+            generic_attributes: opts.sanitize.as_ref().map(|so| &so.generic_attributes[..]),
+            raw_blocks: RefCell::new(vec![]),
+            safe: opts.safe,
+            soft_break: opts.soft_break,
         };
         g.init()
             .map_err(|e| format!("failed to init the html generator: {}", e))?;
@@ -32,12 +87,20 @@ impl<'generator> Generator<'generator> {
         let templates = vec![
             (TP_ORDERED_LIST_NAME, TP_ORDERED_LIST),
             (TP_UNORDERED_LIST_NAME, TP_UNORDERED_LIST),
+            (TP_DEFINITION_LIST_NAME, TP_DEFINITION_LIST),
             (TP_TITLE_NAME, TP_TITLE),
             (TP_QUOTE_NAME, TP_QUOTE),
+            (TP_CALLOUT_NAME, TP_CALLOUT),
+            (TP_CONTAINER_NAME, TP_CONTAINER),
+            (TP_COLUMNS_NAME, TP_COLUMNS),
             (TP_IMG_NAME, TP_IMG),
             (TP_LINK_NAME, TP_LINK),
             (TP_CODE_NAME, TP_CODE),
             (TP_PLAIN_TEXT_NAME, TP_PLAIN_TEXT),
+            (TP_TABLE_NAME, TP_TABLE),
+            (TP_FOOTNOTE_REF_NAME, TP_FOOTNOTE_REF),
+            (TP_FOOTNOTES_NAME, TP_FOOTNOTES),
+            (TP_MATH_NAME, TP_MATH),
         ];
         for (name, tp) in templates {
             self.template.add_template(name, tp)?;
@@ -47,73 +110,38 @@ impl<'generator> Generator<'generator> {
         Ok(())
     }
 
-    fn render_inline(&self, tokens: &Vec<Token>, escape_text: bool) -> String {
-        let mut stack: stack::Stack<(TokenKind, &str)> = stack::Stack::new();
+    fn render_inline(&self, tokens: &[Token]) -> String {
+        // the opened marks with the position of their tags in the buffer
+        let mut stack: stack::Stack<(TokenKind, &str, usize)> = stack::Stack::new();
         let mut buff = String::new();
+        // the raw html to be sanitized, it's marked in the buffer by the 'RAW_HTML_MARK'
+        let mut raws: Vec<&str> = vec![];
 
-        for t in tokens {
+        for (i, t) in tokens.iter().enumerate() {
             match t.kind() {
-                TokenKind::Text | TokenKind::LineBreak => {
-                    if escape_text {
-                        buff.push_str(t.html_escaped_value().as_str())
-                    } else {
-                        buff.push_str(t.value());
-                    }
-                }
-                TokenKind::CodeMark => {
-                    let matched = stack.pop_or_push((t.kind(), t.value()), |e| {
-                        e.0 == t.kind() && e.1 == t.value()
-                    });
-                    if matched.is_some() {
-                        buff.push_str("</code>");
-                    } else {
-                        buff.push_str("<code>");
-                    }
-                }
-                TokenKind::BoldMark => {
-                    let matched = stack.pop_or_push((t.kind(), t.value()), |e| {
-                        e.0 == t.kind() && e.1 == t.value()
-                    });
-                    if matched.is_some() {
-                        buff.push_str("</strong>");
+                TokenKind::Text => buff.push_str(t.html_escaped_value().as_str()),
+                TokenKind::LineBreak => buff.push_str("<br>"),
+                TokenKind::SoftBreak => buff.push_str(self.render_soft_break(tokens, i)),
+                TokenKind::CodeMark
+                | TokenKind::BoldMark
+                | TokenKind::ItalicMark
+                | TokenKind::ItalicBoldMark
+                | TokenKind::DeleteMark => {
+                    let (open, close) = Self::mark_tags(t.kind());
+                    if !t.is_closing() {
+                        stack.push((t.kind(), t.value(), buff.len()));
+                        buff.push_str(open);
+                    } else if stack.pop_if(|e| e.0 == t.kind() && e.1 == t.value()) {
+                        buff.push_str(close);
                     } else {
-                        buff.push_str("<strong>");
-                    }
-                }
-                TokenKind::ItalicMark => {
-                    let matched = stack.pop_or_push((t.kind(), t.value()), |e| {
-                        e.0 == t.kind() && e.1 == t.value()
-                    });
-                    if matched.is_some() {
-                        buff.push_str("</em>");
-                    } else {
-                        buff.push_str("<em>");
-                    }
-                }
-                TokenKind::ItalicBoldMark => {
-                    let matched = stack.pop_or_push((t.kind(), t.value()), |e| {
-                        e.0 == t.kind() && e.1 == t.value()
-                    });
-                    if matched.is_some() {
-                        buff.push_str("</em></strong>");
-                    } else {
-                        buff.push_str("<strong><em>");
-                    }
-                }
-                TokenKind::DeleteMark => {
-                    let matched = stack.pop_or_push((t.kind(), t.value()), |e| {
-                        e.0 == t.kind() && e.1 == t.value()
-                    });
-                    if matched.is_some() {
-                        buff.push_str("</del>");
-                    } else {
-                        buff.push_str("<del>");
+                        // the pair is split, .e.g the cells of table
+                        buff.push_str(t.html_escaped_value().as_str());
                     }
                 }
                 TokenKind::Link | TokenKind::QuickLink | TokenKind::Image => {
                     let link = t.as_generic_link();
 
-                    let s = self.render_inline(&link.name_to_tokens(), false);
+                    let s = self.render_inline(&link.name_to_tokens());
                     let name = if s.is_empty() {
                         link.name()
                     } else {
@@ -121,26 +149,69 @@ impl<'generator> Generator<'generator> {
                     };
                     let location = link.location();
 
+                    let title = link.title();
+
                     if !name.is_empty() && !location.is_empty() {
                         let s = if t.kind() == TokenKind::Image {
-                            self.render_image(name, location)
+                            self.render_image(link.name(), location, title)
                         } else {
-                            self.render_link(name, location)
+                            self.render_link(name, location, title)
                         };
                         buff.push_str(&s);
                     }
                 }
-                TokenKind::RefLink => {
+                TokenKind::RefLink | TokenKind::RefImage => {
                     let link = t.as_generic_link();
-                    let (name, tag) = (link.name(), link.tag());
+                    let name = self.render_inline(&link.name_to_tokens());
 
-                    let default = (String::from(""), String::from(""));
-                    let (location, _title) = self.ref_link_tags.get(tag).unwrap_or(&default);
-                    if !name.is_empty() && !location.is_empty() {
-                        let s = self.render_link(name, location);
-                        buff.push_str(&s);
+                    match self.ref_link_tags.get(&utils::normalize_label(link.tag())) {
+                        Some((location, title)) if !location.is_empty() => {
+                            let s = if t.kind() == TokenKind::RefImage {
+                                self.render_image(link.name(), location, title)
+                            } else {
+                                self.render_link(&name, location, title)
+                            };
+                            buff.push_str(&s);
+                        }
+                        _ => {
+                            // treat the undefined reference as plain text, the name is still parsed,
+                            // .e.g "[**name**][tag]"
+                            let prefix = if t.kind() == TokenKind::RefImage {
+                                "!["
+                            } else {
+                                "["
+                            };
+                            let rest = &t.value()[prefix.len() + link.name().len() + 1..];
+                            buff.push_str(prefix);
+                            buff.push_str(&name);
+                            buff.push(']');
+                            buff.push_str(&htmlescape::escape(rest).to_string());
+                        }
+                    }
+                }
+                TokenKind::FootnoteRef => {
+                    let link = t.as_generic_link();
+                    let label = link.tag();
+                    if self.footnotes.contains_key(label) {
+                        buff.push_str(&self.render_footnote_ref(label));
+                    } else {
+                        // treat the undefined footnote as plain text
+                        buff.push_str(t.html_escaped_value().as_str());
                     }
                 }
+                TokenKind::Math => {
+                    let v = t.value();
+                    let (display, math) = match v.strip_prefix("$$") {
+                        Some(m) => (true, &m[..m.len() - 2]),
+                        None => (false, &v[1..v.len() - 1]),
+                    };
+                    buff.push_str(&self.render_math_text(math, display, true));
+                }
+                TokenKind::Html if self.sanitizer.is_some() => {
+                    raws.push(t.value());
+                    buff.push(RAW_HTML_MARK);
+                }
+                TokenKind::Html => buff.push_str(t.value()),
                 TokenKind::UnorderedMark => match t.second_kind() {
                     Some(TokenKind::TodoDoneMark) => {
                         buff.push_str(r#"<input type="checkbox" disabled checked> "#);
@@ -154,24 +225,267 @@ impl<'generator> Generator<'generator> {
             }
         }
 
+        // the marks without pair are output as they are, so the html is always well-formed
+        for (kind, value, pos) in stack.all_mut().drain(..).rev() {
+            let (open, _) = Self::mark_tags(kind);
+            buff.replace_range(
+                pos..pos + open.len(),
+                &htmlescape::escape(value).to_string(),
+            );
+        }
+
+        if !raws.is_empty() {
+            buff = self.sanitize_mixed(&buff, &raws);
+        }
         buff
     }
 
-    fn render_link(&self, show_name: &str, location: &str) -> String {
+    // Sanitize the raw html mixed with the generated html, the raw html is marked in the 's' by
+    // the 'RAW_HTML_MARK'. The raw html is sanitized together since the tags may be split by the
+    // text or blocks, .e.g "<b>text</b>" or "<details>\n\n*text*\n\n</details>", but the
+    // generated html is replaced by the placeholders when sanitizing, so it's kept as it is.
+    fn sanitize_mixed(&self, s: &str, raws: &[&str]) -> String {
+        let generated: Vec<&str> = s.split(RAW_HTML_MARK).collect();
+        if generated.len() != raws.len() + 1 {
+            // the text contains the mark, so the raw html is dropped to be safe
+            return self.sanitize(&s.replace(RAW_HTML_MARK, ""));
+        }
+
+        let mut html = String::new();
+        for (ix, g) in generated.iter().enumerate() {
+            if ix > 0 {
+                html.push_str(raws[ix - 1]);
+            }
+            if !g.is_empty() {
+                html.push_str(&format!("{}{}{}", GENERATED_BEGIN, ix, GENERATED_END));
+            }
+        }
+
+        // restore the generated html, the placeholders inside the removed tags are dropped,
+        // .e.g "<script>**x**</script>"
+        let html = self.sanitize(&html);
+        let mut buff = String::new();
+        let mut rest = html.as_str();
+        while let Some(pos) = rest.find(GENERATED_BEGIN) {
+            buff.push_str(&rest[..pos]);
+            rest = &rest[pos + GENERATED_BEGIN.len_utf8()..];
+            let end = rest.find(GENERATED_END).unwrap_or(0);
+            match rest[..end]
+                .parse::<usize>()
+                .ok()
+                .and_then(|ix| generated.get(ix))
+            {
+                Some(g) => {
+                    buff.push_str(g);
+                    rest = &rest[end + GENERATED_END.len_utf8()..];
+                }
+                None => buff.push(GENERATED_BEGIN),
+            }
+        }
+        buff.push_str(rest);
+        buff
+    }
+
+    // Render the items of the list, the paragraphs of the items are wrapped in "<p>" if the list
+    // is loose.
+    fn render_list_items(&self, ls: &[SharedLine]) -> Vec<String> {
+        let loose = Line::is_loose_list(ls);
+        ls.iter()
+            .map(|l| {
+                let mut leader = self.render_inline(&l.borrow().list_item_tokens());
+                if loose && !leader.is_empty() {
+                    leader = self
+                        .template
+                        .render(TP_PLAIN_TEXT_NAME, &PlainTextContext { text: leader })
+                        .unwrap();
+                }
+                let nesting = l.borrow().enter_nested_blocks(self, loose);
+                if leader.is_empty() {
+                    // the item begins with a block, .e.g "- > quote"
+                    nesting
+                } else if !nesting.is_empty() {
+                    leader + "\n" + nesting.as_str()
+                } else {
+                    leader
+                }
+            })
+            .collect()
+    }
+
+    // Render the soft line break at the index of the tokens. In the "cjk" mode, the lines are
+    // joined directly if the characters on both sides are CJK, the marks of emphasis are skipped.
+    fn render_soft_break(&self, tokens: &[Token], i: usize) -> &'static str {
+        match self.soft_break {
+            SoftBreak::Newline => "\n",
+            SoftBreak::Br => "<br>",
+            SoftBreak::Cjk => {
+                let is_text = |t: &&Token| !Self::is_mark(t.kind());
+                let prev = tokens[..i].iter().rev().find(is_text);
+                let next = tokens[i + 1..].iter().find(is_text);
+                let cjk = |t: Option<&Token>, c: fn(&str) -> Option<char>| {
+                    t.filter(|t| t.kind() == TokenKind::Text)
+                        .and_then(|t| c(t.value()))
+                        .is_some_and(utils::is_cjk)
+                };
+                if cjk(prev, |s| s.chars().last()) && cjk(next, |s| s.chars().next()) {
+                    ""
+                } else {
+                    "\n"
+                }
+            }
+        }
+    }
+
+    // Determine whether the token is the mark of emphasis, deletion or code
+    fn is_mark(kind: TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::CodeMark
+                | TokenKind::BoldMark
+                | TokenKind::ItalicMark
+                | TokenKind::ItalicBoldMark
+                | TokenKind::DeleteMark
+        )
+    }
+
+    // Get the opening and closing tags of the mark
+    fn mark_tags(kind: TokenKind) -> (&'static str, &'static str) {
+        match kind {
+            TokenKind::CodeMark => ("<code>", "</code>"),
+            TokenKind::BoldMark => ("<strong>", "</strong>"),
+            TokenKind::ItalicMark => ("<em>", "</em>"),
+            TokenKind::ItalicBoldMark => ("<strong><em>", "</em></strong>"),
+            TokenKind::DeleteMark => ("<del>", "</del>"),
+            _ => unreachable!(),
+        }
+    }
+
+    fn sanitize(&self, s: &str) -> String {
+        match self.sanitizer.as_ref() {
+            Some(b) => b.clean(s).to_string(),
+            None => s.to_string(),
+        }
+    }
+
+    // Render the attributes of the element, .e.g ' class="a b" data-x="1"'. The classes in the
+    // attributes follow the 'class' of the element, only the id, class and 'data-*' attributes
+    // are kept in the safe mode, and only the generic attributes of the sanitizer are kept if
+    // it's configured.
+    fn render_attributes(&self, class: &str, attrs: &[(String, String)]) -> String {
+        let mut classes: Vec<&str> = vec![class];
+        let mut others = String::new();
+        for (k, v) in attrs.iter().filter(|(k, _)| self.is_allowed_attribute(k)) {
+            if k == "class" {
+                classes.push(v);
+            } else {
+                others.push_str(&format!(" {}=\"{}\"", k, utils::escape_attribute(v)));
+            }
+        }
+
+        classes.retain(|c| !c.is_empty());
+        if classes.is_empty() {
+            others
+        } else {
+            let class = utils::escape_attribute(&classes.join(" "));
+            format!(" class=\"{}\"{}", class, others)
+        }
+    }
+
+    // Determine whether the attribute is allowed by the safe mode and the sanitizer
+    fn is_allowed_attribute(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        (!self.safe || Self::is_safe_attribute(&name))
+            && self
+                .generic_attributes
+                .is_none_or(|attrs| attrs.iter().any(|a| a.eq_ignore_ascii_case(&name)))
+    }
+
+    // Determine whether the attribute is allowed in the safe mode, the others may run the scripts
+    // or load the urls, .e.g "onclick", "href" and "style".
+    fn is_safe_attribute(name: &str) -> bool {
+        name == "id" || name == "class" || name.starts_with("data-")
+    }
+
+    // The location is escaped as the attribute value, it's also percent-encoded in the safe mode.
+    // Only the show name is output if the location is unsafe.
+    fn render_link(&self, show_name: &str, location: &str, title: &str) -> String {
+        let location = match self.escape_location(location, false) {
+            Some(l) => l,
+            None => return show_name.to_string(),
+        };
         self.template
             .render(
                 TP_LINK_NAME,
                 &LinkContext {
                     show_name,
-                    location,
+                    location: &location,
+                    title: &utils::escape_attribute(title),
                 },
             )
             .unwrap()
     }
 
-    fn render_image(&self, alt: &str, location: &str) -> String {
+    // The alt is the raw text of the image name, the image is output as its alt text if the
+    // location is unsafe.
+    fn render_image(&self, alt: &str, location: &str, title: &str) -> String {
+        let location = match self.escape_location(location, true) {
+            Some(l) => l,
+            None => return htmlescape::escape(alt).to_string(),
+        };
         self.template
-            .render(TP_IMG_NAME, &ImageContext { alt, location })
+            .render(
+                TP_IMG_NAME,
+                &ImageContext {
+                    alt: &utils::escape_attribute(alt),
+                    location: &location,
+                    title: &utils::escape_attribute(title),
+                },
+            )
+            .unwrap()
+    }
+
+    // Escape the location of the link or image, return None if it's unsafe in the safe mode
+    fn escape_location(&self, location: &str, is_image: bool) -> Option<String> {
+        if !self.safe {
+            return Some(utils::escape_attribute(location));
+        }
+        if !utils::is_safe_url(location, is_image) {
+            return None;
+        }
+        Some(utils::escape_url(location))
+    }
+
+    // Render the math with the delimiters of KaTeX, "\\(...\\)" is inline and "\\[...\\]" is display.
+    fn render_math_text(&self, math: &str, display: bool, inline: bool) -> String {
+        self.template
+            .render(
+                TP_MATH_NAME,
+                &MathContext {
+                    display,
+                    inline,
+                    text: &htmlescape::escape(math.trim()).to_string(),
+                },
+            )
+            .unwrap()
+    }
+
+    // Footnotes are numbered in order of the first reference, the first reference is the target
+    // of the back-link.
+    fn render_footnote_ref(&self, label: &str) -> String {
+        let mut refs = self.footnote_refs.borrow_mut();
+        // the footnote is rendered only after its first reference, so it's rendered only once in
+        // the slices, .e.g "fn-1" is referenced by the first and second slices
+        let (num, id) = match refs.iter().position(|e| e == label) {
+            Some(ix) => (ix + 1, String::new()),
+            None => {
+                refs.push(label.to_string());
+                self.footnote_pending.borrow_mut().push(refs.len());
+                (refs.len(), format!("fnref-{}", refs.len()))
+            }
+        };
+
+        self.template
+            .render(TP_FOOTNOTE_REF_NAME, &FootnoteRefContext { num, id: &id })
             .unwrap()
     }
 }
@@ -180,7 +494,14 @@ impl<'generator> Generate for Generator<'generator> {
     fn render_title(&self, l: &SharedLine) -> String {
         let l = l.borrow();
         let level = l.mark_token().len();
-        let value = self.render_inline(l.all(), true);
+        let value = self.render_inline(l.all());
+        // the id is rendered by the anchor that prefers the custom id
+        let attrs: Vec<(String, String)> = l
+            .attributes()
+            .iter()
+            .filter(|(k, _)| k != "id")
+            .cloned()
+            .collect();
 
         let ctx = TitleContext {
             is_l1: level == 1,
@@ -189,7 +510,8 @@ impl<'generator> Generate for Generator<'generator> {
             is_l4: level == 4,
             is_l5: level == 5,
             is_l6: level == 6,
-            id: l.anchor().0,
+            id: utils::escape_attribute(&l.anchor().0),
+            attrs: self.render_attributes("", &attrs),
             text: value,
         };
 
@@ -201,69 +523,71 @@ impl<'generator> Generate for Generator<'generator> {
     }
 
     fn render_plain_text(&self, ls: &[SharedLine]) -> String {
-        let mut lines: Vec<String> = ls
-            .iter()
-            .map(|l| {
-                let mut s = self.render_inline(l.borrow().all(), false);
-                if !s.ends_with("<br>") {
-                    s.push_str("<br>");
-                }
-                s
-            })
-            .collect();
-
-        // remove the trailing '<br>' of the last element
-        // TODO: optimize
-        if let Some(last) = lines.pop() {
-            lines.push(last.trim_end_matches("<br>").to_string());
+        let tokens = Line::paragraph_tokens(ls);
+        if tokens.is_empty() {
+            // all lines are the definitions of link or footnote
+            return String::new();
         }
 
+        let text = self.render_inline(&tokens);
         self.template
-            .render(TP_PLAIN_TEXT_NAME, &PlainTextContext { lines })
+            .render(TP_PLAIN_TEXT_NAME, &PlainTextContext { text })
             .unwrap()
     }
 
+    fn render_tight_text(&self, ls: &[SharedLine]) -> String {
+        self.render_inline(&Line::paragraph_tokens(ls))
+    }
+
     fn render_blank(&self, _ls: &[SharedLine]) -> String {
         String::from("")
     }
 
     fn render_ordered_list(&self, ls: &[SharedLine]) -> String {
-        let list: Vec<String> = ls
-            .iter()
-            .map(|l| {
-                let leader = self.render_inline(l.borrow().all(), true);
-                let nesting = l
-                    .borrow()
-                    .enter_nested_blocks(&Generator::new(self.ref_link_tags).unwrap());
-                if !nesting.is_empty() {
-                    leader + "\n" + nesting.as_str()
-                } else {
-                    leader
-                }
-            })
-            .collect();
+        let start = ls
+            .first()
+            .and_then(|l| l.borrow().list_start())
+            .filter(|n| *n != 1)
+            .map(|n| n.to_string());
+        let list = self.render_list_items(ls);
         self.template
-            .render(TP_ORDERED_LIST_NAME, &OrderedListContext { list })
+            .render(TP_ORDERED_LIST_NAME, &OrderedListContext { start, list })
             .unwrap()
     }
 
     fn render_unordered_list(&self, ls: &[SharedLine]) -> String {
+        let list = self.render_list_items(ls);
+        self.template
+            .render(TP_UNORDERED_LIST_NAME, &UnorderedListContext { list })
+            .unwrap()
+    }
+
+    fn render_definition_list(&self, ls: &[SharedLine]) -> String {
+        let loose = Line::is_loose_list(ls);
         let list = ls
             .iter()
-            .map(|l| {
-                let leader = self.render_inline(l.borrow().all(), true);
-                let nesting = l
-                    .borrow()
-                    .enter_nested_blocks(&Generator::new(self.ref_link_tags).unwrap());
-                if !nesting.is_empty() {
-                    leader + "\n" + nesting.as_str()
+            .filter_map(|sl| {
+                let l = sl.borrow();
+                if l.is_description() {
+                    let text = l.enter_nested_blocks(self, loose);
+                    Some(DefinitionItemContext {
+                        is_term: false,
+                        text,
+                    })
+                } else if l.is_term() {
+                    let tokens = Line::paragraph_tokens(std::slice::from_ref(sl));
+                    let text = self.render_inline(&tokens);
+                    Some(DefinitionItemContext {
+                        is_term: true,
+                        text,
+                    })
                 } else {
-                    leader
+                    None
                 }
             })
             .collect();
         self.template
-            .render(TP_UNORDERED_LIST_NAME, &UnorderedListContext { list })
+            .render(TP_DEFINITION_LIST_NAME, &DefinitionListContext { list })
             .unwrap()
     }
 
@@ -273,36 +597,338 @@ impl<'generator> Generate for Generator<'generator> {
             .unwrap()
     }
 
+    fn render_callout(&self, kind: &str, title: &str, s: &str) -> String {
+        let title = self.render_inline(&Lexer::new(title).split_inline_only());
+        self.template
+            .render(
+                TP_CALLOUT_NAME,
+                &CalloutContext {
+                    kind,
+                    title,
+                    text: s,
+                },
+            )
+            .unwrap()
+    }
+
+    fn render_container(
+        &self,
+        name: &str,
+        title: &str,
+        attrs: &[(String, String)],
+        s: &str,
+    ) -> String {
+        let (tag, title) = match name {
+            "details" if title.is_empty() => ("details", "Details".to_string()),
+            "details" => ("details", title.to_string()),
+            _ if ASIDE_CONTAINERS.contains(&name) && title.is_empty() => {
+                ("aside", name[..1].to_uppercase() + &name[1..])
+            }
+            _ if ASIDE_CONTAINERS.contains(&name) => ("aside", title.to_string()),
+            _ => ("div", title.to_string()),
+        };
+
+        let ctx = ContainerContext {
+            tag,
+            attrs: self.render_attributes(&format!("container container-{}", name), attrs),
+            is_details: tag == "details",
+            title: self.render_inline(&Lexer::new(&title).split_inline_only()),
+            text: s,
+        };
+        self.template.render(TP_CONTAINER_NAME, &ctx).unwrap()
+    }
+
+    fn render_columns(&self, attrs: &[(String, String)], columns: &[(u32, String)]) -> String {
+        let columns = columns
+            .iter()
+            .map(|(ratio, text)| ColumnContext {
+                ratio: *ratio,
+                text,
+            })
+            .collect();
+        self.template
+            .render(
+                TP_COLUMNS_NAME,
+                &ColumnsContext {
+                    attrs: self.render_attributes("columns", attrs),
+                    columns,
+                },
+            )
+            .unwrap()
+    }
+
     fn render_code(&self, ls: &[SharedLine]) -> String {
-        debug_assert!(ls.len() >= 2);
+        debug_assert!(!ls.is_empty());
+
+        // the fenced code block begins with an opening mark and may end with a closing mark,
+        // the indented code block only contains the content lines.
+        let (info, contents) = if ls[0].borrow().is_fence() {
+            let end = if ls.len() > 1 && ls[ls.len() - 1].borrow().is_fence() {
+                ls.len() - 1
+            } else {
+                ls.len()
+            };
+            (ls[0].borrow().code_info().to_string(), &ls[1..end])
+        } else {
+            (String::new(), ls)
+        };
 
-        let first = &ls[0];
-        let text: String = ls[1..ls.len() - 1] // skip the first and last elements
+        let info = CodeInfo::parse(&info);
+        let contents: Vec<String> = contents
             .iter()
-            .map(|l| htmlescape::escape(l.borrow().text()).to_string())
+            .map(|l| l.borrow().code_text().to_string())
             .collect();
 
+        // the highlighted code is escaped already, and the plain code is escaped here
+        let highlighted = self.highlighter.as_ref().and_then(|h| {
+            let lines: Vec<&str> = contents.iter().map(|l| l.as_str()).collect();
+            h.highlight(info.lang, &lines)
+                .map(|texts| (texts, h.block_style()))
+        });
+        let (texts, style) = highlighted.unwrap_or_else(|| {
+            let texts = contents
+                .iter()
+                .map(|l| htmlescape::escape(l).to_string())
+                .collect();
+            (texts, String::new())
+        });
+
+        // the lines are wrapped one by one only if the line numbers or highlighted lines are needed
+        let lines = if info.linenos || !info.highlights.is_empty() {
+            texts
+                .iter()
+                .enumerate()
+                .map(|(ix, text)| CodeLineContext {
+                    num: ix + 1,
+                    highlighted: info.is_highlighted(ix + 1),
+                    text: text.trim_end_matches('\n').to_string(),
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
         self.template
             .render(
                 TP_CODE_NAME,
                 &CodeBlockContext {
-                    name: first.borrow().get(1).map(|t| t.value()).unwrap_or(""),
-                    text: &text,
+                    name: &htmlescape::escape(info.lang).to_string(),
+                    title: &htmlescape::escape(&info.title).to_string(),
+                    linenos: info.linenos,
+                    style: &style,
+                    text: &texts.concat(),
+                    lines,
                 },
             )
             .unwrap()
     }
+
+    fn render_table(&self, ls: &[SharedLine]) -> String {
+        debug_assert!(ls.len() >= 2);
+
+        // the first line is header row, the second line is delimiter row
+        let aligns = ls[1].borrow().table_aligns();
+        let columns = aligns.len();
+
+        let render_row = |l: &SharedLine| -> Vec<TableCellContext> {
+            let l = l.borrow();
+            let cells = l.table_cells();
+            // the excess cells are ignored, and the missing cells are filled with empty
+            (0..columns)
+                .map(|i| TableCellContext {
+                    align: aligns[i],
+                    text: cells
+                        .get(i)
+                        .map(|c| self.render_inline(c))
+                        .unwrap_or_default(),
+                })
+                .collect()
+        };
+
+        let ctx = TableContext {
+            head: render_row(&ls[0]),
+            rows: ls[2..].iter().map(render_row).collect(),
+        };
+        self.template.render(TP_TABLE_NAME, &ctx).unwrap()
+    }
+
+    // The raw html block is marked and sanitized with the whole contents later, so the tags that
+    // wrap the markdown are kept, .e.g "<div>\n\n*text*\n\n</div>"
+    fn render_raw_html(&self, ls: &[SharedLine]) -> String {
+        let text: String = ls.iter().map(|l| l.borrow().text().to_string()).collect();
+        let text = text.trim_end();
+        if self.sanitizer.is_none() {
+            return text.to_string();
+        }
+        self.raw_blocks.borrow_mut().push(text.to_string());
+        RAW_HTML_MARK.to_string()
+    }
+
+    fn render_contents(&self, s: String) -> String {
+        let raws: Vec<String> = self.raw_blocks.borrow_mut().drain(..).collect();
+        if raws.is_empty() {
+            return s;
+        }
+        let raws: Vec<&str> = raws.iter().map(|r| r.as_str()).collect();
+        self.sanitize_mixed(&s, &raws)
+    }
+
+    fn render_math(&self, ls: &[SharedLine]) -> String {
+        let text: String = ls.iter().map(|l| l.borrow().text().to_string()).collect();
+        let text = text.trim();
+        let math = text
+            .strip_prefix("$$")
+            .and_then(|s| s.strip_suffix("$$"))
+            .unwrap_or(text);
+        self.render_math_text(math, true, false)
+    }
+
+    fn render_footnotes(&self) -> String {
+        let mut list: Vec<FootnoteContext> = vec![];
+        // the text of a footnote may reference other footnotes, so they're rendered until no
+        // footnote is pending
+        loop {
+            let mut pending: Vec<usize> = self
+                .footnote_pending
+                .borrow_mut()
+                .drain(..)
+                .filter(|num| list.iter().all(|f| f.num != *num))
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+            pending.sort_unstable();
+
+            // the refs are borrowed mutably when the text of footnote is rendered
+            let labels: Vec<(usize, String)> = {
+                let refs = self.footnote_refs.borrow();
+                pending
+                    .into_iter()
+                    .map(|num| (num, refs[num - 1].clone()))
+                    .collect()
+            };
+            for (num, label) in labels {
+                let text = self
+                    .footnotes
+                    .get(&label)
+                    .map(|s| self.render_inline(&Lexer::new(s).split_inline_only()))
+                    .unwrap_or_default();
+                list.push(FootnoteContext { num, text });
+            }
+        }
+        if list.is_empty() {
+            return String::new();
+        }
+        list.sort_by_key(|f| f.num);
+
+        self.template
+            .render(TP_FOOTNOTES_NAME, &FootnotesContext { list })
+            .unwrap()
+    }
 }
 
+// The info string of the fenced code block, .e.g:
+//      ```rust {1,3-5} linenos title="main.rs"
+// the first word is the language, the others are the attributes.
+#[derive(Debug, Default, PartialEq)]
+struct CodeInfo<'code_info> {
+    lang: &'code_info str,
+    title: String,
+    linenos: bool,
+    // the ranges of the highlighted line numbers, both ends are inclusive
+    highlights: Vec<(usize, usize)>,
+}
+
+impl<'code_info> CodeInfo<'code_info> {
+    fn parse(info: &'code_info str) -> Self {
+        let mut ci = CodeInfo::default();
+
+        for (ix, word) in Self::split_words(info).into_iter().enumerate() {
+            if word.starts_with('{') {
+                ci.highlights.append(&mut Self::parse_ranges(word));
+            } else if word == "linenos" {
+                ci.linenos = true;
+            } else if let Some(v) = word.strip_prefix("title=") {
+                ci.title = v.trim_matches(|c| c == '"' || c == '\'').to_string();
+            } else if ix == 0 {
+                // the language may be followed by the highlighted lines, .e.g "rust{1,3}"
+                match word.find('{') {
+                    Some(pos) => {
+                        ci.lang = &word[..pos];
+                        ci.highlights.append(&mut Self::parse_ranges(&word[pos..]));
+                    }
+                    None => ci.lang = word,
+                }
+            }
+        }
+        ci
+    }
+
+    // Split the info string by white space, but the white space in the quotes or braces is kept
+    fn split_words(info: &str) -> Vec<&str> {
+        let mut words = vec![];
+        let mut closing: Option<char> = None;
+        let mut begin: Option<usize> = None;
+
+        for (ix, c) in info.char_indices() {
+            match closing {
+                Some(x) if x == c => closing = None,
+                Some(_) => {}
+                None if c.is_whitespace() => {
+                    if let Some(b) = begin.take() {
+                        words.push(&info[b..ix]);
+                    }
+                }
+                None => {
+                    begin.get_or_insert(ix);
+                    closing = match c {
+                        '"' | '\'' => Some(c),
+                        '{' => Some('}'),
+                        _ => None,
+                    };
+                }
+            }
+        }
+        if let Some(b) = begin {
+            words.push(&info[b..]);
+        }
+        words
+    }
+
+    // Parse the highlighted lines, .e.g "{1,3-5}", the invalid ranges are ignored
+    fn parse_ranges(s: &str) -> Vec<(usize, usize)> {
+        s.trim_start_matches('{')
+            .trim_end_matches('}')
+            .split(',')
+            .filter_map(|r| {
+                let (a, b) = r.split_once('-').unwrap_or((r, r));
+                let a = a.trim().parse::<usize>().ok()?;
+                let b = b.trim().parse::<usize>().ok()?;
+                Some((a.min(b), a.max(b)))
+            })
+            .collect()
+    }
+
+    fn is_highlighted(&self, num: usize) -> bool {
+        self.highlights.iter().any(|(a, b)| *a <= num && num <= *b)
+    }
+}
+
+// The private use characters mark the raw html and the generated html when sanitizing the inline
+// html, they're not output.
+const RAW_HTML_MARK: char = '\u{E000}';
+const GENERATED_BEGIN: char = '\u{E001}';
+const GENERATED_END: char = '\u{E002}';
+
 // title
 const TP_TITLE_NAME: &str = "title";
 const TP_TITLE: &str = "\
-{{ if is_l1 }}<h1 id=\"{id}\">{text}</h1>{{ endif }}\
-{{ if is_l2 }}<h2 id=\"{id}\">{text}</h2>{{ endif }}\
-{{ if is_l3 }}<h3 id=\"{id}\">{text}</h3>{{ endif }}\
-{{ if is_l4 }}<h4 id=\"{id}\">{text}</h4>{{ endif }}\
-{{ if is_l5 }}<h5 id=\"{id}\">{text}</h5>{{ endif }}\
-{{ if is_l6 }}<h6 id=\"{id}\">{text}</h6>{{ endif }}";
+{{ if is_l1 }}<h1 id=\"{id}\"{attrs}>{text}</h1>{{ endif }}\
+{{ if is_l2 }}<h2 id=\"{id}\"{attrs}>{text}</h2>{{ endif }}\
+{{ if is_l3 }}<h3 id=\"{id}\"{attrs}>{text}</h3>{{ endif }}\
+{{ if is_l4 }}<h4 id=\"{id}\"{attrs}>{text}</h4>{{ endif }}\
+{{ if is_l5 }}<h5 id=\"{id}\"{attrs}>{text}</h5>{{ endif }}\
+{{ if is_l6 }}<h6 id=\"{id}\"{attrs}>{text}</h6>{{ endif }}";
 
 #[derive(Serialize)]
 struct TitleContext {
@@ -313,13 +939,14 @@ struct TitleContext {
     is_l5: bool,
     is_l6: bool,
     id: String,
+    attrs: String,
     text: String,
 }
 
 // ordered list
 const TP_ORDERED_LIST_NAME: &str = "ordered_list";
 const TP_ORDERED_LIST: &str = "\
-<ol>\
+<ol{{ if start }} start=\"{start}\"{{ endif }}>\
 {{ for item in list }}
     <li>{item}</li>\
 {{ endfor }}
@@ -327,6 +954,8 @@ const TP_ORDERED_LIST: &str = "\
 
 #[derive(Serialize)]
 struct OrderedListContext {
+    // the number of the first item, it's None if the list starts with 1
+    start: Option<String>,
     list: Vec<String>,
 }
 
@@ -344,60 +973,627 @@ struct UnorderedListContext {
     list: Vec<String>,
 }
 
+// definition list
+const TP_DEFINITION_LIST_NAME: &str = "definition_list";
+const TP_DEFINITION_LIST: &str = "\
+<dl>\
+{{ for item in list }}
+    {{ if item.is_term }}<dt>{item.text}</dt>{{ else }}<dd>{item.text}</dd>{{ endif }}\
+{{ endfor }}
+</dl>";
+
+#[derive(Serialize)]
+struct DefinitionListContext {
+    list: Vec<DefinitionItemContext>,
+}
+
+#[derive(Serialize)]
+struct DefinitionItemContext {
+    is_term: bool,
+    text: String,
+}
+
 // link
 const TP_LINK_NAME: &str = "link";
-const TP_LINK: &str = r#"<a href="{location}">{show_name}</a>"#;
+const TP_LINK: &str =
+    r#"<a href="{location}"{{ if title }} title="{title}"{{ endif }}>{show_name}</a>"#;
 
 #[derive(Serialize)]
 struct LinkContext<'link_context> {
     show_name: &'link_context str,
     location: &'link_context str,
+    title: &'link_context str,
 }
 
 // image
 const TP_IMG_NAME: &str = "img";
-const TP_IMG: &str = r#"<img src="{location}" alt="{alt}">"#;
+const TP_IMG: &str =
+    r#"<img src="{location}" alt="{alt}"{{ if title }} title="{title}"{{ endif }}>"#;
 
 #[derive(Serialize)]
 struct ImageContext<'image_context> {
     alt: &'image_context str,
     location: &'image_context str,
+    title: &'image_context str,
 }
 
 // code block
 const TP_CODE_NAME: &str = "code_block";
-const TP_CODE: &str = "<pre><code>
+const TP_CODE: &str = "\
+{{ if title }}<div class=\"code-title\">{title}</div>
+{{ endif }}\
+<pre{{ if linenos }} class=\"linenos\"{{ endif }}{{ if style }} style=\"{style}\"{{ endif }}><code{{ if name }} class=\"language-{name}\"{{ endif }}>
+{{ if lines }}\
+{{ for line in lines }}\
+<span class=\"line{{ if line.highlighted }} highlighted{{ endif }}\">\
+{{ if linenos }}<span class=\"lineno\">{line.num}</span>{{ endif }}\
+{line.text}</span>
+{{ endfor }}\
+{{ else }}\
 {text}\
+{{ endif }}\
 </code></pre>";
 
 #[derive(Serialize)]
 struct CodeBlockContext<'code_block_context> {
     name: &'code_block_context str,
+    title: &'code_block_context str,
+    linenos: bool,
+    style: &'code_block_context str,
     text: &'code_block_context str,
+    lines: Vec<CodeLineContext>,
+}
+
+#[derive(Serialize)]
+struct CodeLineContext {
+    num: usize,
+    highlighted: bool,
+    text: String,
 }
 
 // plain text
 const TP_PLAIN_TEXT_NAME: &str = "plain_text";
-const TP_PLAIN_TEXT: &str = "\
-<p>\
-{{ for text in lines}}\
-{text}\
-{{ endfor }}\
-</p>";
+const TP_PLAIN_TEXT: &str = "<p>{text}</p>";
 
 #[derive(Serialize)]
 struct PlainTextContext {
-    lines: Vec<String>,
+    text: String,
 }
 
 // quote block
 const TP_QUOTE_NAME: &str = "quote";
 const TP_QUOTE: &str = "\
-<blockquote><p>
-    {text}
-</p></blockquote>";
+<blockquote>
+{text}
+</blockquote>";
 
 #[derive(Serialize)]
 struct QuoteContext<'quote_context> {
     text: &'quote_context str,
 }
+
+// callout, the class names are the same as the alerts of GitHub
+const TP_CALLOUT_NAME: &str = "callout";
+const TP_CALLOUT: &str = "\
+<div class=\"markdown-alert markdown-alert-{kind}\">
+<p class=\"markdown-alert-title\">{title}</p>
+{text}
+</div>";
+
+#[derive(Serialize)]
+struct CalloutContext<'callout_context> {
+    kind: &'callout_context str,
+    title: String,
+    text: &'callout_context str,
+}
+
+// container, the containers of the names are rendered as "<aside>", the "details" is rendered as
+// "<details>" and the others are rendered as "<div>".
+const ASIDE_CONTAINERS: [&str; 7] = [
+    "note",
+    "tip",
+    "info",
+    "important",
+    "warning",
+    "danger",
+    "caution",
+];
+
+const TP_CONTAINER_NAME: &str = "container";
+const TP_CONTAINER: &str = "\
+<{tag}{attrs}>
+{{ if is_details }}<summary>{title}</summary>
+{{ else }}{{ if title }}<p class=\"container-title\">{title}</p>
+{{ endif }}{{ endif }}\
+{text}
+</{tag}>";
+
+#[derive(Serialize)]
+struct ContainerContext<'container_context> {
+    tag: &'static str,
+    // the attributes are rendered already, .e.g ' class="container container-tip" id="intro"'
+    attrs: String,
+    is_details: bool,
+    title: String,
+    text: &'container_context str,
+}
+
+// columns, they're laid out by the flex box and the width of column is in proportion to its ratio
+const TP_COLUMNS_NAME: &str = "columns";
+const TP_COLUMNS: &str = "\
+<div{attrs} style=\"display: flex;\">\
+{{ for column in columns }}
+<div class=\"column\" style=\"flex: {column.ratio}; min-width: 0;\">
+{column.text}
+</div>\
+{{ endfor }}
+</div>";
+
+#[derive(Serialize)]
+struct ColumnsContext<'columns_context> {
+    // the attributes are rendered already, .e.g ' class="columns wide" id="intro"'
+    attrs: String,
+    columns: Vec<ColumnContext<'columns_context>>,
+}
+
+#[derive(Serialize)]
+struct ColumnContext<'column_context> {
+    ratio: u32,
+    text: &'column_context str,
+}
+
+// table
+const TP_TABLE_NAME: &str = "table";
+const TP_TABLE: &str = "\
+<table>
+<thead>
+<tr>\
+{{ for cell in head }}<th{{ if cell.align }} align=\"{cell.align}\"{{ endif }}>{cell.text}</th>{{ endfor }}\
+</tr>
+</thead>\
+{{ if rows }}
+<tbody>\
+{{ for row in rows }}
+<tr>\
+{{ for cell in row }}<td{{ if cell.align }} align=\"{cell.align}\"{{ endif }}>{cell.text}</td>{{ endfor }}\
+</tr>\
+{{ endfor }}
+</tbody>\
+{{ endif }}
+</table>";
+
+#[derive(Serialize)]
+struct TableContext {
+    head: Vec<TableCellContext>,
+    rows: Vec<Vec<TableCellContext>>,
+}
+
+#[derive(Serialize)]
+struct TableCellContext {
+    align: &'static str,
+    text: String,
+}
+
+// footnote reference
+const TP_FOOTNOTE_REF_NAME: &str = "footnote_ref";
+const TP_FOOTNOTE_REF: &str = "\
+<sup class=\"footnote-ref\"><a href=\"#fn-{num}\"{{ if id }} id=\"{id}\"{{ endif }}>{num}</a></sup>";
+
+#[derive(Serialize)]
+struct FootnoteRefContext<'footnote_ref_context> {
+    num: usize,
+    id: &'footnote_ref_context str,
+}
+
+// footnotes section
+const TP_FOOTNOTES_NAME: &str = "footnotes";
+const TP_FOOTNOTES: &str = "\
+<section class=\"footnotes\">
+<ol>\
+{{ for item in list }}
+    <li id=\"fn-{item.num}\" value=\"{item.num}\">{item.text} <a href=\"#fnref-{item.num}\" class=\"footnote-backref\">&#8617;</a></li>\
+{{ endfor }}
+</ol>
+</section>";
+
+#[derive(Serialize)]
+struct FootnotesContext {
+    list: Vec<FootnoteContext>,
+}
+
+#[derive(Serialize)]
+struct FootnoteContext {
+    num: usize,
+    text: String,
+}
+
+// math
+const TP_MATH_NAME: &str = "math";
+const TP_MATH: &str = "\
+{{ if inline }}<span{{ else }}<div{{ endif }} class=\"math {{ if display }}math-display\">\\[{text}\\]{{ else }}math-inline\">\\({text}\\){{ endif }}\
+{{ if inline }}</span>{{ else }}</div>{{ endif }}";
+
+#[derive(Serialize)]
+struct MathContext<'math_context> {
+    display: bool,
+    inline: bool,
+    text: &'math_context str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{SanitizeOptions, TocOptions};
+    use crate::parser::Ast;
+
+    fn render(md: &str, opts: &HtmlOptions) -> String {
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let generator = Generator::new(ast.ref_link_tags(), ast.footnotes(), opts).unwrap();
+        ast.generate_content(&generator)
+    }
+
+    #[test]
+    fn test_code_info() {
+        let ci = CodeInfo::parse(r#"rust {1,3-5} linenos title="src/main.rs""#);
+        assert_eq!(
+            ci,
+            CodeInfo {
+                lang: "rust",
+                title: "src/main.rs".to_string(),
+                linenos: true,
+                highlights: vec![(1, 1), (3, 5)],
+            }
+        );
+        assert!(ci.is_highlighted(4));
+        assert!(!ci.is_highlighted(2));
+
+        // the title with white space, the ranges after the language and the reversed range
+        let ci = CodeInfo::parse("js{2, 7-6} title='a b.js'");
+        assert_eq!(ci.lang, "js");
+        assert_eq!(ci.title, "a b.js");
+        assert!(!ci.linenos);
+        assert_eq!(ci.highlights, vec![(2, 2), (6, 7)]);
+
+        // the invalid ranges are ignored
+        assert_eq!(CodeInfo::parse("{x,2-y,4}").highlights, vec![(4, 4)]);
+        assert_eq!(CodeInfo::parse(""), CodeInfo::default());
+    }
+
+    #[test]
+    fn test_highlight_code() {
+        let md = "```rust {2} linenos\nfn main() {\n    let a = \"<b>\";\n}\n```\n";
+        let opts = HtmlOptions {
+            highlight_theme: Some("InspiredGitHub".to_string()),
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+
+        assert!(s.starts_with("<pre class=\"linenos\" style=\"color: #"));
+        assert!(s.contains("<span class=\"line highlighted\"><span class=\"lineno\">2</span>"));
+        // the tokens are styled by the theme, the colors are not a concern of the test
+        let styled = s.find("<span style=\"").unwrap();
+        assert!(s.find("<pre").is_some_and(|ix| ix < styled));
+        assert!(s.contains("\">fn </span>"));
+        assert!(s.contains("&quot;&lt;b&gt;&quot;"));
+        assert_eq!(s.matches("<span class=\"lineno\">").count(), 3);
+    }
+
+    #[test]
+    fn test_ref_link() {
+        let md = "[*Foo* Bar][foo bar] [FOO BAR][] [foo  bar] ![Logo][LOGO] [*x*][none]\n\n\
+                  [foo bar]: /foo \"Foo\"\n[Foo Bar]: /ignored\n[logo]: /logo.png 'The \"logo\"'\n";
+        let s = render(md, &HtmlOptions::default());
+        assert_eq!(
+            s,
+            "<p><a href=\"/foo\" title=\"Foo\"><em>Foo</em> Bar</a> \
+             <a href=\"/foo\" title=\"Foo\">FOO BAR</a> \
+             <a href=\"/foo\" title=\"Foo\">foo  bar</a> \
+             <img src=\"/logo.png\" alt=\"Logo\" title=\"The &quot;logo&quot;\"> \
+             [<em>x</em>][none]</p>"
+        );
+
+        let s = render("[a](/a \"A & B\")", &HtmlOptions::default());
+        assert_eq!(s, "<p><a href=\"/a\" title=\"A &amp; B\">a</a></p>");
+
+        // the reference ends at the first ']'
+        let s = render("[foo]] [a]](/a)\n\n[foo]: /url\n", &HtmlOptions::default());
+        assert_eq!(s, "<p><a href=\"/url\">foo</a>] <a href=\"/a\">a]</a></p>");
+    }
+
+    #[test]
+    fn test_soft_break() {
+        let md = "中文\n标题\n\n中文\nLatin\n\nLatin\ntext\n\n**中文**\n*标题*\n";
+        let cases = [
+            (
+                SoftBreak::Cjk,
+                vec![
+                    "<p>中文标题</p>",
+                    "<p>中文\nLatin</p>",
+                    "<p>Latin\ntext</p>",
+                    "<p><strong>中文</strong><em>标题</em></p>",
+                ],
+            ),
+            (
+                SoftBreak::Newline,
+                vec![
+                    "<p>中文\n标题</p>",
+                    "<p>中文\nLatin</p>",
+                    "<p>Latin\ntext</p>",
+                    "<p><strong>中文</strong>\n<em>标题</em></p>",
+                ],
+            ),
+            (
+                SoftBreak::Br,
+                vec![
+                    "<p>中文<br>标题</p>",
+                    "<p>中文<br>Latin</p>",
+                    "<p>Latin<br>text</p>",
+                    "<p><strong>中文</strong><br><em>标题</em></p>",
+                ],
+            ),
+        ];
+        for (soft_break, dest) in cases {
+            let opts = HtmlOptions {
+                soft_break,
+                ..Default::default()
+            };
+            assert_eq!(render(md, &opts), dest.join("\n\n"));
+        }
+    }
+
+    #[test]
+    fn test_toc_escape() {
+        let md = "# Intro & Setup\n## a < b `<c>`\n";
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let opts = HtmlOptions::default();
+        let generator = Generator::new(ast.ref_link_tags(), ast.footnotes(), &opts).unwrap();
+        let s = ast.generate_toc(&TocOptions::default(), &generator);
+
+        assert!(s.contains(r##"<a href="#intro--setup">Intro &amp; Setup</a>"##));
+        assert!(s.contains(r##"<a href="#a--b-c">a &lt; b <code>&lt;c&gt;</code></a>"##));
+    }
+
+    #[test]
+    fn test_quote() {
+        let cases = [
+            // the nested quote and the lazy continuation line of its paragraph
+            (
+                "> a\n>> b\nlazy\n> c\n",
+                "<blockquote>\n<p>a</p>\n\n<blockquote>\n<p>b<br>lazy<br>c</p>\n</blockquote>\n\
+                 </blockquote>",
+            ),
+            // the quote in the list item
+            (
+                "- item\n  > in list\n  lazy\n",
+                "<ul>\n    <li>item\n<blockquote>\n<p>in list<br>lazy</p>\n</blockquote></li>\n</ul>",
+            ),
+            // the list, fenced code and table in the quote
+            (
+                "> - x\n>\n> ```rs\n> let a = 1;\n> ```\n> | a |\n> |---|\n> | 1 |\n",
+                "<blockquote>\n<ul>\n    <li>x</li>\n</ul>\n\n\
+                 <pre><code class=\"language-rs\">\nlet a = 1;\n</code></pre>\n\n\
+                 <table>\n<thead>\n<tr><th>a</th></tr>\n</thead>\n\
+                 <tbody>\n<tr><td>1</td></tr>\n</tbody>\n</table>\n</blockquote>",
+            ),
+        ];
+        for (md, dest) in cases {
+            assert_eq!(render(md, &HtmlOptions::default()), dest, "{}", md);
+        }
+
+        // the definitions in the quote are available in the whole document
+        let md = "> [a] and[^n]\n>\n> [a]: /url\n> [^n]: note\n\n[a][]\n";
+        let s = render(md, &HtmlOptions::default());
+        assert!(s.starts_with(
+            "<blockquote>\n<p><a href=\"/url\">a</a> and<sup class=\"footnote-ref\">\
+             <a href=\"#fn-1\" id=\"fnref-1\">1</a></sup></p>\n</blockquote>\n\n\
+             <p><a href=\"/url\">a</a></p>"
+        ));
+        assert!(s.contains("<li id=\"fn-1\" value=\"1\">note"));
+    }
+
+    #[test]
+    fn test_slice_footnotes() {
+        let md = "a[^1]\n\n---\n\nb[^1] c[^2]\n\n[^1]: one\n[^2]: two\n";
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let opts = HtmlOptions::default();
+        let generator = Generator::new(ast.ref_link_tags(), ast.footnotes(), &opts).unwrap();
+        let slices = ast.generate_slice(&generator);
+
+        // the footnote is rendered in the first slice that references it
+        assert_eq!(slices.len(), 2);
+        assert!(slices[0].contains("<li id=\"fn-1\" value=\"1\">one"));
+        assert!(!slices[1].contains("id=\"fn-1\""));
+        assert!(slices[1].contains("<li id=\"fn-2\" value=\"2\">two"));
+        assert_eq!(slices.concat().matches("id=\"fnref-1\"").count(), 1);
+    }
+
+    #[test]
+    fn test_nested_footnotes() {
+        let md = "text[^a]\n\n[^a]: see[^b] and[^a]\n[^b]: the end\n";
+        let s = render(md, &HtmlOptions::default());
+
+        assert_eq!(s.matches("<li id=\"fn-").count(), 2);
+        assert!(s.contains("<li id=\"fn-1\" value=\"1\">see"));
+        assert!(s.contains("<li id=\"fn-2\" value=\"2\">the end"));
+    }
+
+    #[test]
+    fn test_sanitize_inline() {
+        let md = "[a](https://a.com) <b onclick=\"x()\">**b**</b> ![c](c.png)[^1] <script>**d**</script>`<e>`\n\n[^1]: note\n";
+        let opts = HtmlOptions {
+            sanitize: Some(SanitizeOptions {
+                tags: vec!["b".to_string()],
+                generic_attributes: vec![],
+                tag_attributes: HashMap::new(),
+            }),
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+
+        // the generated html is kept, only the raw html is sanitized
+        let dest = "<p><a href=\"https://a.com\">a</a> <b><strong>b</strong></b> \
+<img src=\"c.png\" alt=\"c\">\
+<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup> \
+<code>&lt;e&gt;</code></p>";
+        assert!(s.starts_with(dest));
+    }
+
+    #[test]
+    fn test_sanitize_blocks() {
+        let md = "<details>\n<summary>S</summary>\n\n*md*\n\n</details>\n\n\
+                  <div class=\"box\" onclick=\"x()\">\n\n- a\n- b\n\n</div>\n\n\
+                  <script>\nalert(1)\n</script>\n\n<p>unclosed\n";
+        let opts = HtmlOptions {
+            sanitize: Some(SanitizeOptions::default()),
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+
+        // the wrappers are kept around the markdown content
+        let dest = "<details>\n<summary>S</summary>\n\n<p><em>md</em></p>\n\n</details>\n\n\
+                    <div class=\"box\">\n\n<ul>\n    <li>a</li>\n    <li>b</li>\n</ul>\n\n</div>\n\n\
+                    \n\n<p>unclosed</p>";
+        assert_eq!(s, dest);
+    }
+
+    #[test]
+    fn test_safe_links() {
+        let opts = HtmlOptions {
+            safe: true,
+            ..Default::default()
+        };
+        let cases = [
+            // the link with the unsafe url is output as its name
+            ("[x](javascript:alert(1))", "<p>x</p>"),
+            ("![y](javascript:alert(1))", "<p>y</p>"),
+            (
+                "[a](https://x.com/a_(b) \"t\")",
+                "<p><a href=\"https://x.com/a_(b)\" title=\"t\">a</a></p>",
+            ),
+        ];
+        for (md, dest) in cases {
+            assert_eq!(render(md, &opts), dest, "{}", md);
+        }
+    }
+
+    #[test]
+    fn test_safe_raw_html() {
+        let md = "<img src=x onerror=alert(1)> <b onclick=\"x()\">b</b>\n\n\
+                  <div onclick=\"x()\">\n<script>alert(1)</script>\n</div>\n";
+        let s = render(md, &HtmlOptions::default());
+        assert!(s.contains("onerror") && s.contains("<script>"));
+
+        // the raw html is sanitized by the default policy in the safe mode
+        let opts = HtmlOptions {
+            safe: true,
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+        assert_eq!(s, "<p><img src=\"x\"> <b>b</b></p>\n\n<div>\n\n</div>");
+
+        // the urls in the raw html have the same schemes as the links in the safe mode
+        let md = "<a href=\"javascript:alert(1)\">a</a> <a href=\"irc://x\">b</a> \
+                  <a href=\"https://x.com\">c</a>";
+        let s = render(md, &opts);
+        assert_eq!(
+            s,
+            "<p><a rel=\"noopener noreferrer\">a</a> <a rel=\"noopener noreferrer\">b</a> \
+             <a href=\"https://x.com\" rel=\"noopener noreferrer\">c</a></p>"
+        );
+        let opts = HtmlOptions {
+            sanitize: Some(SanitizeOptions::default()),
+            ..Default::default()
+        };
+        assert!(render(md, &opts).contains("irc://x"));
+    }
+
+    #[test]
+    fn test_safe_attributes() {
+        let md = r#"::: note {#n .wide data-x="1" onclick="x()" href="javascript:alert(1)" style="color: red"}
+text
+:::
+"#;
+        let s = render(md, &HtmlOptions::default());
+        assert!(s.contains(r#"onclick="x()""#));
+        assert!(s.contains(r#"href="javascript:alert(1)""#));
+
+        let opts = HtmlOptions {
+            safe: true,
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+        assert!(s.starts_with(r#"<aside class="container container-note wide" id="n" data-x="1">"#));
+        assert!(!s.contains("onclick"));
+        assert!(!s.contains("javascript"));
+        assert!(!s.contains("style"));
+    }
+
+    #[test]
+    fn test_sanitized_attributes() {
+        let md = r#"# Title {#t .big data-x="1" onclick="x()"}
+
+::: note {#n .wide data-x="1" onclick="x()" style="color: red"}
+text
+:::
+
+::: columns {#c .wide onclick="x()"}
+left
+:::
+"#;
+        let opts = HtmlOptions {
+            sanitize: Some(SanitizeOptions::default()),
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+        assert!(s.starts_with(r#"<h1 id="t" class="big">Title</h1>"#));
+        assert!(s.contains(r#"<aside class="container container-note wide" id="n">"#));
+        assert!(s.contains(r#"<div class="columns wide" id="c" style="display: flex;">"#));
+        assert!(!s.contains("onclick"));
+        assert!(!s.contains("data-x"));
+        assert!(!s.contains("color: red"));
+
+        // only the generic attributes of the sanitizer are kept
+        let opts = HtmlOptions {
+            sanitize: Some(SanitizeOptions {
+                generic_attributes: vec!["data-x".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+        assert!(s.starts_with(r#"<h1 id="t" data-x="1">Title</h1>"#));
+        assert!(s.contains(r#"<aside class="container container-note" data-x="1">"#));
+    }
+
+    #[test]
+    fn test_title_attributes() {
+        let md = r#"## B {#b .big data-x="1" style="color: red" onclick="x()"}"#;
+        let s = render(md, &HtmlOptions::default());
+        assert_eq!(
+            s,
+            r#"<h2 id="b" class="big" data-x="1" style="color: red" onclick="x()">B</h2>"#
+        );
+
+        let opts = HtmlOptions {
+            safe: true,
+            ..Default::default()
+        };
+        let s = render(md, &opts);
+        assert_eq!(s, r#"<h2 id="b" class="big" data-x="1">B</h2>"#);
+    }
+
+    #[test]
+    fn test_columns_attributes() {
+        let md = "::: columns 1:2 {#cols .wide data-x=\"1\"}\nleft\n::: column\nright\n:::\n:::\n";
+        let s = render(md, &HtmlOptions::default());
+        let dest =
+            "<div class=\"columns wide\" id=\"cols\" data-x=\"1\" style=\"display: flex;\">\n\
+<div class=\"column\" style=\"flex: 1; min-width: 0;\">\n<p>left</p>\n</div>\n\
+<div class=\"column\" style=\"flex: 2; min-width: 0;\">\n<p>right</p>\n</div>\n\
+</div>";
+        assert_eq!(s, dest);
+    }
+}
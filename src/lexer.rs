@@ -1,12 +1,12 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 
-use crate::utils::{self, cursor, stack};
+use crate::utils::{self, cursor};
 
 use itertools::Itertools;
 use v_htmlescape as htmlescape;
 
-const ESCAPE_CHARS: &str = "~:*_`#+-.![]()<>\\";
+const ESCAPE_CHARS: &str = "~:*_`#+-.![]()<>$\\";
 
 #[derive(PartialEq, Debug)]
 enum State {
@@ -31,8 +31,8 @@ enum InlineState {
     LinkNameBegin(usize),
     // means ], (usize, usize, usize) is the index of ('!', '[', ']')
     NameEnd(Option<usize>, usize, usize),
-    // means [, (usize, usize, usize) is the index of ('[', ']', '[')
-    RefLink(usize, usize, usize),
+    // means [, (usize, usize, usize, usize) is the index of ('!', '[', ']', '[')
+    RefLink(Option<usize>, usize, usize, usize),
     // means :, (usize, usize, usize) is the index of ('[', ']', ':')
     RefLinkDef(usize, usize, usize),
     // means (, (usize, usize, usize, usize) is the index of ('!', '[', ']', '(')
@@ -45,6 +45,19 @@ enum InlineState {
     Stopped,
 }
 
+impl InlineState {
+    // Determine whether the state can continue on the next line, .e.g "[long\nlink](url)"
+    fn spans_lines(&self) -> bool {
+        matches!(
+            self,
+            InlineState::LinkNameBegin(_)
+                | InlineState::ImgNameBegin(..)
+                | InlineState::Location(..)
+                | InlineState::RefLink(..)
+        )
+    }
+}
+
 // Lexer is a lexical analyzer that parses lines of text into multiple tokens.
 pub(crate) struct Lexer<'lexer> {
     state: State,
@@ -71,7 +84,12 @@ impl<'lexer> Lexer<'lexer> {
                         if !s.is_empty() {
                             buff.push(Token::new(s, TokenKind::WhiteSpace));
                         }
-                        self.goto(State::Word(ix));
+                        // the word of one character is followed by the end of line
+                        if iter.peek().is_some_and(|(_, n)| *n == '\n') {
+                            self.goto(State::Mark(ix, ix + 1));
+                        } else {
+                            self.goto(State::Word(ix));
+                        }
                     } else {
                         // the end of iterator
                         if curr == '\n' {
@@ -109,7 +127,9 @@ impl<'lexer> Lexer<'lexer> {
                                     };
                                     self.goto(State::Stop(pos));
                                 }
-                                TokenKind::CodeBlockMark => self.goto(State::Inline(begin + 3)),
+                                TokenKind::CodeBlockMark => {
+                                    self.goto(State::Inline(begin + m.len()))
+                                }
                                 TokenKind::DividingMark => self.goto(State::Finished),
                                 TokenKind::UnorderedMark => match m.second_kind() {
                                     None => self.goto(State::Inline(end + 1)),
@@ -139,7 +159,15 @@ impl<'lexer> Lexer<'lexer> {
                 }
             }
             State::Inline(begin) => {
-                let rest = self.slice_rest(begin);
+                let mut rest = self.slice_rest(begin).to_string();
+                if buff
+                    .last()
+                    .filter(|t| t.kind() == TokenKind::TitleMark)
+                    .is_some()
+                {
+                    rest = Self::trim_title_closing(&rest);
+                }
+                let rest = rest.as_str();
                 let mut tokens = Self::split_inline(rest, 0);
                 if Self::has_br(rest) {
                     tokens.push(Token::new("<br>".to_string(), TokenKind::LineBreak));
@@ -155,6 +183,19 @@ impl<'lexer> Lexer<'lexer> {
         buff
     }
 
+    // Parse the whole text as inline content, the marks at the beginning of the text
+    // (.e.g title, list and quote) are not recognized.
+    pub(crate) fn split_inline_only(self) -> Vec<Token> {
+        let mut text = self.line_text.to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        let mut tokens = Self::split_inline(&text, 0);
+        Self::tidy(&mut tokens);
+        tokens.into_iter().filter(|t| !t.is_empty()).collect()
+    }
+
     // Parse the first word in the line as the mark token
     fn split_mark(line_text: &str, first_word: &str) -> Option<Token> {
         let first_word_chars: Vec<char> = first_word.chars().collect();
@@ -170,31 +211,23 @@ impl<'lexer> Lexer<'lexer> {
                 Some(Token::new(first_word.to_string(), TokenKind::TitleMark))
             }
 
-            // Ordered List
-            [n1, '.'] if ('1'..='9').contains(&n1) => {
-                Some(Token::new(first_word.to_string(), TokenKind::OrderedMark))
-            }
-            [n1, n2, '.'] if ('1'..='9').contains(&n1) && ('0'..='9').contains(&n2) => {
-                Some(Token::new(first_word.to_string(), TokenKind::OrderedMark))
-            }
-            [n1, n2, n3, '.']
-                if ('1'..='9').contains(&n1)
-                    && ('0'..='9').contains(&n2)
-                    && ('0'..='9').contains(&n3) =>
+            // Ordered List, up to 9 digits followed by '.' or ')', .e.g "1." "2)" "1000."
+            [ref n @ .., '.' | ')']
+                if (1..=9).contains(&n.len()) && n.iter().all(|c| c.is_ascii_digit()) =>
             {
                 Some(Token::new(first_word.to_string(), TokenKind::OrderedMark))
             }
 
-            // Quote
-            ['>', ..] if first_word_chars.iter().dedup().count() == 1 => {
-                Some(Token::new(">".to_string(), TokenKind::QuoteMark))
-            }
+            // Quote, the mark may be followed by the content directly, .e.g ">text" or ">> text"
+            ['>', ..] => Some(Token::new(">".to_string(), TokenKind::QuoteMark)),
 
             // Code Block
             // .e.g:
             //      ```rust
             //      ``` rust
-            ['`', '`', '`', ..] => Some(Token::new("```".to_string(), TokenKind::CodeBlockMark)),
+            //      ~~~~
+            ['`', '`', '`', ..] | ['~', '~', '~', ..] => Self::split_fence(line_text)
+                .map(|(fence, _)| Token::new(fence.to_string(), TokenKind::CodeBlockMark)),
 
             // Dividing Line
             // Unordered List
@@ -237,12 +270,91 @@ impl<'lexer> Lexer<'lexer> {
         }
     }
 
+    // Parse the fence of the code block, it's a sequence of at least three backticks(`) or
+    // tildes(~) and indented with up to three spaces. The info string of backtick fence can't
+    // contain any backtick.
+    // Return the fence and the width of its indentation.
+    pub(crate) fn split_fence(line_text: &str) -> Option<(&str, usize)> {
+        let indent = line_text.len() - line_text.trim_start_matches(' ').len();
+        if indent > 3 {
+            return None;
+        }
+
+        let s = &line_text[indent..];
+        let c = s.chars().next().filter(|c| *c == '`' || *c == '~')?;
+        let n = s.chars().take_while(|x| *x == c).count();
+        if n < 3 || (c == '`' && s[n..].contains('`')) {
+            return None;
+        }
+        Some((&s[..n], indent))
+    }
+
+    // Find the end of the inline math that begins at 'begin', .e.g "$x^2$" or "$$x^2$$".
+    // The content can't be empty, the content of "$x$" can't begin or end with white space and
+    // the closing '$' can't be followed by a digit, so "$5 and $6" is not a math.
+    // Return the index of the last '$'.
+    fn find_math_end(content: &str, begin: usize) -> Option<usize> {
+        let chars: Vec<char> = content.chars().skip(begin).collect();
+        let n = if chars.get(1) == Some(&'$') { 2 } else { 1 };
+        if n == 1 && chars.get(1).is_none_or(|c| c.is_whitespace()) {
+            return None;
+        }
+
+        let mut ix = n;
+        // the math can't cross the line or the code span
+        while ix < chars.len() && chars[ix] != '\n' && chars[ix] != '`' {
+            match chars[ix] {
+                '\\' => ix += 1, // skip the escaped character
+                '$' if n == 2 && chars.get(ix + 1) == Some(&'$') && ix > n => {
+                    return Some(begin + ix + 1);
+                }
+                '$' if n == 1
+                    && !chars[ix - 1].is_whitespace()
+                    && !chars.get(ix + 1).is_some_and(|c| c.is_ascii_digit()) =>
+                {
+                    return Some(begin + ix);
+                }
+                _ => (),
+            }
+            ix += 1;
+        }
+        None
+    }
+
+    // Open or close the code span by the backticks that begin at 'begin' and have 'n' characters.
+    // The code span is opened only if there are the same number of backticks behind it.
+    fn toggle_code_span(
+        content: &str,
+        begin: usize,
+        n: usize,
+        opened: Option<usize>,
+    ) -> Option<usize> {
+        match opened {
+            Some(m) if m == n => None,
+            Some(m) => Some(m),
+            None => {
+                let rest: String = content.chars().skip(begin + n).collect();
+                let closed = rest.split(|c| c != '`').any(|run| run.len() == n);
+                if closed {
+                    Some(n)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     // Parse inline syntax, include bold, image and link etc.
     fn split_inline(content: &str, skip: usize) -> Vec<Token> {
         let mut cursor = cursor::Cursor::new(content);
 
         let mut buff: Vec<Token> = Vec::new();
         let mut state = InlineState::Plain;
+        // the length of backticks that opened the current code span, the math is not parsed in it
+        let mut code_span: Option<usize> = None;
+
+        // the content may contain multiple lines, the last character is always '\n'
+        let last_ix = content.chars().count().saturating_sub(1);
 
         let mut cnt_iter = content.chars().enumerate().skip(skip).peekable();
         while let Some((curr_ix, curr)) = cnt_iter.next() {
@@ -264,17 +376,27 @@ impl<'lexer> Lexer<'lexer> {
                 (InlineState::Skip, _) => {
                     state = InlineState::Plain;
                 }
-                (_, '\n') => {
+                (_, '\n') if curr_ix == last_ix || !state.spans_lines() => {
                     // end of the line
-                    let s = cursor
-                        .slice_to(curr_ix)
-                        .trim_end()
-                        .trim_end_matches("<br>")
-                        .to_string();
+                    let line = cursor.slice_to(curr_ix);
+                    let s = line.trim_end().trim_end_matches("<br>").to_string();
                     if !s.is_empty() {
                         buff.push(Token::new(s, TokenKind::Text));
                     }
-                    state = InlineState::Stopped;
+                    if curr_ix == last_ix {
+                        state = InlineState::Stopped;
+                    } else {
+                        // the line break between the lines, it's a hard break if the line ends
+                        // with double spaces or "<br>"
+                        let t = if Self::has_br(&format!("{}\n", line)) {
+                            Token::new("<br>".to_string(), TokenKind::LineBreak)
+                        } else {
+                            Token::new("\n".to_string(), TokenKind::SoftBreak)
+                        };
+                        buff.push(t);
+                        cursor.consume_to(curr_ix + 1, |_| ());
+                        state = InlineState::Plain;
+                    }
                 }
                 (_, '\\') => {
                     let next = cnt_iter.peek().filter(|(_, n)| ESCAPE_CHARS.contains(*n));
@@ -297,6 +419,9 @@ impl<'lexer> Lexer<'lexer> {
                         if cnt_iter.peek().filter(|(_, n)| *n == curr).is_some() {
                             state = InlineState::Continuous(curr_ix);
                         } else {
+                            if curr == '`' {
+                                code_span = Self::toggle_code_span(content, curr_ix, 1, code_span);
+                            }
                             // current -> next
                             cursor.consume_to(curr_ix + 1, |s| {
                                 let k = match curr {
@@ -310,8 +435,45 @@ impl<'lexer> Lexer<'lexer> {
                             });
                         }
                     }
+                    '$' if code_span.is_none() => {
+                        if let Some(end) = Self::find_math_end(content, curr_ix) {
+                            // cursor -> current
+                            cursor.consume_to(curr_ix, |s| {
+                                buff.push(Token::new(s.to_string(), TokenKind::Text))
+                            });
+                            // current -> end of the math
+                            cursor.consume_to(end + 1, |s| {
+                                buff.push(Token::new(s.to_string(), TokenKind::Math))
+                            });
+                            // the content of math is not parsed
+                            while cnt_iter.next_if(|(ix, _)| *ix <= end).is_some() {}
+                        }
+                    }
                     '!' => state = InlineState::ImgBegin(curr_ix),
                     '[' => state = InlineState::LinkNameBegin(curr_ix),
+                    '<' if code_span.is_none() => {
+                        let rest = utf8_slice::from(content, curr_ix);
+                        // the trailing "<br>" of the line is a line break
+                        let tag = utils::match_html_tag(rest).filter(|t| {
+                            let line_rest = rest[t.len()..].split('\n').next().unwrap_or("");
+                            !(*t == "<br>" && line_rest.trim().is_empty())
+                        });
+                        match tag {
+                            Some(tag) => {
+                                let end = curr_ix + tag.chars().count();
+                                // cursor -> current
+                                cursor.consume_to(curr_ix, |s| {
+                                    buff.push(Token::new(s.to_string(), TokenKind::Text))
+                                });
+                                // current -> end of the tag
+                                cursor.consume_to(end, |s| {
+                                    buff.push(Token::new(s.to_string(), TokenKind::Html))
+                                });
+                                while cnt_iter.next_if(|(ix, _)| *ix < end).is_some() {}
+                            }
+                            None => state = InlineState::QuickLink(curr_ix),
+                        }
+                    }
                     '<' => state = InlineState::QuickLink(curr_ix),
                     _ => (),
                 },
@@ -322,64 +484,126 @@ impl<'lexer> Lexer<'lexer> {
                 },
                 (InlineState::ImgNameBegin(b1, b2), _) => {
                     if curr == ']' {
-                        state = InlineState::NameEnd(Some(b1), b2, curr_ix);
+                        let next = cnt_iter.peek().map(|(_, n)| *n);
+                        state = Self::end_link_name(
+                            &mut cursor,
+                            &mut buff,
+                            content,
+                            next,
+                            (Some(b1), b2, curr_ix),
+                        );
                     } else {
                         // determine whether the next charater is '\n'
-                        if cnt_iter.peek().filter(|(_, v)| *v == '\n').is_some() {
+                        if cnt_iter.peek().is_some_and(|(ix, _)| *ix == last_ix) {
                             state = InlineState::FallBack(b2 + 1);
                         }
                     }
                 }
                 (InlineState::LinkNameBegin(begin), _) => match curr {
-                    ']' => state = InlineState::NameEnd(None, begin, curr_ix),
+                    ']' => {
+                        let name = utf8_slice::slice(content, begin + 1, curr_ix);
+                        if !Self::is_footnote_label(name) {
+                            let next = cnt_iter.peek().map(|(_, n)| *n);
+                            state = Self::end_link_name(
+                                &mut cursor,
+                                &mut buff,
+                                content,
+                                next,
+                                (None, begin, curr_ix),
+                            );
+                        } else if begin == 0 && cnt_iter.peek().filter(|(_, n)| *n == ':').is_some()
+                        {
+                            // the footnote definition, .e.g "[^1]: text"
+                            let end = Self::line_end(content, curr_ix);
+                            let text = utf8_slice::slice(content, curr_ix + 2, end).trim();
+                            cursor.consume_to(end, |s| {
+                                let mut t = Token::new(s.to_string(), TokenKind::FootnoteDef);
+                                t.as_generic_link_mut().insert_reflink_tag(&name[1..]);
+                                t.as_generic_link_mut().insert_name(text);
+                                buff.push(t);
+                            });
+                            while cnt_iter.next_if(|(ix, _)| *ix < end).is_some() {}
+
+                            state = InlineState::Plain;
+                        } else {
+                            // the footnote reference, .e.g "[^1]"
+                            // cursor -> begin
+                            cursor.consume_to(begin, |s| {
+                                buff.push(Token::new(s.to_string(), TokenKind::Text));
+                            });
+                            // begin -> next
+                            cursor.consume_to(curr_ix + 1, |s| {
+                                let mut t = Token::new(s.to_string(), TokenKind::FootnoteRef);
+                                t.as_generic_link_mut().insert_reflink_tag(&name[1..]);
+                                buff.push(t);
+                            });
+
+                            state = InlineState::Plain;
+                        }
+                    }
                     '[' => state = InlineState::LinkNameBegin(curr_ix),
                     _ => {
                         // determine whether the next charater is '\n'
-                        if cnt_iter.peek().filter(|(_, v)| *v == '\n').is_some() {
+                        if cnt_iter.peek().is_some_and(|(ix, _)| *ix == last_ix) {
                             state = InlineState::FallBack(begin + 1);
                         }
                     }
                 },
                 (InlineState::NameEnd(b1, b2, b3), _) => match curr {
                     '(' => state = InlineState::Location(b1, b2, b3, curr_ix),
-                    ']' => state = InlineState::NameEnd(b1, b2, curr_ix),
-                    '[' => state = InlineState::RefLink(b2, b3, curr_ix),
+                    ']' => {
+                        let next = cnt_iter.peek().map(|(_, n)| *n);
+                        state = Self::end_link_name(
+                            &mut cursor,
+                            &mut buff,
+                            content,
+                            next,
+                            (b1, b2, curr_ix),
+                        );
+                    }
+                    '[' => state = InlineState::RefLink(b1, b2, b3, curr_ix),
                     ':' => state = InlineState::RefLinkDef(b2, b3, curr_ix),
                     _ => state = InlineState::FallBack(b2 + 1),
                 },
-                (InlineState::RefLink(b1, b2, b3), _) => {
+                (InlineState::RefLink(b1, b2, b3, b4), _) => {
                     if curr == ']' {
-                        // cursor -> b1
-                        cursor.consume_to(b1, |s| {
-                            buff.push(Token::new(s.to_string(), TokenKind::Text));
-                        });
-                        // b1 -> next
-                        cursor.consume_to(curr_ix + 1, |s| {
-                            let s1 = utf8_slice::slice(content, b1 + 1, b2);
-                            let s2 = utf8_slice::slice(content, b3 + 1, curr_ix);
-                            let t = Self::split_generic_link(s, s1, s2, TokenKind::RefLink);
-                            buff.push(t);
-                        });
+                        let s2 = utf8_slice::slice(content, b4 + 1, curr_ix);
+                        Self::push_ref_link(
+                            &mut cursor,
+                            &mut buff,
+                            content,
+                            (b1, b2, b3),
+                            s2,
+                            curr_ix,
+                        );
 
                         state = InlineState::Plain;
                     } else {
                         // determine whether the next charater is '\n'
-                        if cnt_iter.peek().filter(|(_, v)| *v == '\n').is_some() {
-                            state = InlineState::FallBack(b1 + 1);
+                        if cnt_iter.peek().is_some_and(|(ix, _)| *ix == last_ix) {
+                            state = InlineState::FallBack(b2 + 1);
                         }
                     }
                 }
                 (InlineState::RefLinkDef(b1, b2, _b3), _) => {
-                    let s = cursor.rest_slice().trim_end_matches('\n');
+                    // the definition takes the rest of the line
+                    let end = Self::line_end(content, curr_ix);
                     let s1 = utf8_slice::slice(content, b1 + 1, b2);
-                    let s2 = utf8_slice::from(content, curr_ix).trim_end_matches('\n');
-                    let t = Self::split_generic_link(s, s1, s2, TokenKind::RefLinkDef);
-                    buff.push(t);
+                    let s2 = utf8_slice::slice(content, curr_ix, end);
+                    cursor.consume_to(end, |s| {
+                        buff.push(Self::split_generic_link(s, s1, s2, TokenKind::RefLinkDef));
+                    });
+                    while cnt_iter.next_if(|(ix, _)| *ix < end).is_some() {}
 
-                    state = InlineState::Stopped;
+                    state = InlineState::Plain;
                 }
                 (InlineState::Location(b1, b2, b3, b4), _) => {
-                    if curr == ')' {
+                    // the parentheses in the location are balanced before the title, .e.g
+                    // "[a](https://example.com/a_(b))"
+                    let location = utf8_slice::slice(content, b4 + 1, curr_ix).trim_start();
+                    let nested = !location.contains(char::is_whitespace)
+                        && location.matches('(').count() > location.matches(')').count();
+                    if curr == ')' && !nested {
                         let begin = b1.unwrap_or(b2);
                         // cursor -> begin
                         cursor.consume_to(begin, |s| {
@@ -400,7 +624,7 @@ impl<'lexer> Lexer<'lexer> {
                         state = InlineState::Plain;
                     } else {
                         // determine whether the next charater is '\n'
-                        if cnt_iter.peek().filter(|(_, v)| *v == '\n').is_some() {
+                        if cnt_iter.peek().is_some_and(|(ix, _)| *ix == last_ix) {
                             state = InlineState::FallBack(b2 + 1);
                         }
                     }
@@ -429,7 +653,7 @@ impl<'lexer> Lexer<'lexer> {
                         state = InlineState::Plain;
                     } else {
                         // determine whether the next charater is '\n'
-                        if cnt_iter.peek().filter(|(_, v)| *v == '\n').is_some() {
+                        if cnt_iter.peek().is_some_and(|(ix, _)| *ix == last_ix) {
                             state = InlineState::FallBack(begin + 1);
                         }
                     }
@@ -438,6 +662,10 @@ impl<'lexer> Lexer<'lexer> {
                     debug_assert_eq!(cursor.index(), begin);
 
                     if cnt_iter.peek().filter(|(_, n)| *n == curr).is_none() {
+                        if curr == '`' {
+                            let n = curr_ix + 1 - begin;
+                            code_span = Self::toggle_code_span(content, begin, n, code_span);
+                        }
                         cursor.consume_to(curr_ix + 1, |s| {
                             let k = match curr {
                                 '*' => TokenKind::Star,
@@ -457,6 +685,65 @@ impl<'lexer> Lexer<'lexer> {
         buff
     }
 
+    // Determine the state after the closing ']' of the link name, the `name` is the index of
+    // ('!', '[', ']'). It's a shortcut reference if the next character doesn't continue the link,
+    // .e.g "[tag]" and "![tag]", then the reference is pushed directly. The following ']' only
+    // continues the name of the inline link, .e.g "[a]](url)", the reference ends at the first
+    // ']', .e.g "[tag]]" is the reference "tag" followed by the text "]".
+    fn end_link_name(
+        cursor: &mut cursor::Cursor,
+        buff: &mut Vec<Token>,
+        content: &str,
+        next: Option<char>,
+        name: (Option<usize>, usize, usize),
+    ) -> InlineState {
+        // the name starts with '^' is reserved for the footnote, .e.g "[^1]"
+        let footnote = utf8_slice::slice(content, name.1 + 1, name.2).starts_with('^');
+        match next {
+            _ if footnote => InlineState::NameEnd(name.0, name.1, name.2),
+            Some('(' | '[' | ':') => InlineState::NameEnd(name.0, name.1, name.2),
+            Some(']')
+                if utf8_slice::from(content, name.2 + 1)
+                    .trim_start_matches(']')
+                    .starts_with('(') =>
+            {
+                InlineState::NameEnd(name.0, name.1, name.2)
+            }
+            _ => {
+                Self::push_ref_link(cursor, buff, content, name, "", name.2);
+                InlineState::Plain
+            }
+        }
+    }
+
+    // Push the reference link or image, the `name` is the index of ('!', '[', ']') and the `end`
+    // is the index of the last character. The `tag` is empty in the collapsed and shortcut
+    // reference, .e.g "[tag][]" and "[tag]".
+    fn push_ref_link(
+        cursor: &mut cursor::Cursor,
+        buff: &mut Vec<Token>,
+        content: &str,
+        name: (Option<usize>, usize, usize),
+        tag: &str,
+        end: usize,
+    ) {
+        let (b1, b2, b3) = name;
+        // cursor -> begin
+        cursor.consume_to(b1.unwrap_or(b2), |s| {
+            buff.push(Token::new(s.to_string(), TokenKind::Text));
+        });
+        // begin -> next
+        cursor.consume_to(end + 1, |s| {
+            let s1 = utf8_slice::slice(content, b2 + 1, b3);
+            let kind = if b1.is_some() {
+                TokenKind::RefImage
+            } else {
+                TokenKind::RefLink
+            };
+            buff.push(Self::split_generic_link(s, s1, tag, kind));
+        });
+    }
+
     // the argument `s` is the whole string of the link
     // .e.g. `[s1](s2)`
     // the argument `s1` is the name of the link
@@ -464,10 +751,19 @@ impl<'lexer> Lexer<'lexer> {
     fn split_generic_link(s: &str, s1: &str, s2: &str, kind: TokenKind) -> Token {
         let s2 = s2.trim();
 
+        if kind == TokenKind::RefLink || kind == TokenKind::RefImage {
+            // the tag is the name in the collapsed and shortcut reference
+            let tag = if s2.is_empty() { s1 } else { s2 };
+            let mut t = Token::new(s.to_string(), kind);
+            t.as_generic_link_mut().insert_name(s1);
+            t.as_generic_link_mut().insert_reflink_tag(tag);
+            return t;
+        }
+
         // split the location into two parts
         // the first part is the link, the second part is the title
         // .e.g. `https://example.com "title"`
-        let fields: Vec<&str> = s2.splitn(2, [' ', '\t']).collect();
+        let fields: Vec<&str> = s2.splitn(2, [' ', '\t', '\n']).collect();
         let (kind, location, title) = match fields.len().cmp(&2) {
             Ordering::Less => (kind, s2, ""),
             Ordering::Equal => {
@@ -488,10 +784,6 @@ impl<'lexer> Lexer<'lexer> {
                 rf.as_generic_link_mut().insert_location(location);
                 rf.as_generic_link_mut().insert_title(title);
             }
-            TokenKind::RefLink => {
-                rf.as_generic_link_mut().insert_name(s1);
-                rf.as_generic_link_mut().insert_reflink_tag(s2);
-            }
             TokenKind::RefLinkDef => {
                 rf.as_generic_link_mut().insert_reflink_tag(s1);
                 rf.as_generic_link_mut().insert_location(location);
@@ -508,93 +800,97 @@ impl<'lexer> Lexer<'lexer> {
     }
 
     fn tidy(buff: &mut Vec<Token>) {
-        Self::tidy_continuous_mark(TokenKind::Star, buff);
-        Self::tidy_continuous_mark(TokenKind::UnderLine, buff);
-
-        let mut stack: stack::Stack<&mut Token> = stack::Stack::new();
-
-        let buff_iter = buff.iter_mut().filter(|t| {
-            t.kind() == TokenKind::Star
-                || t.kind() == TokenKind::UnderLine
-                || t.kind() == TokenKind::BackTick
-                || t.kind() == TokenKind::Tilde
-        });
+        Self::tidy_code_spans(buff);
+        Self::tidy_delimiter_runs(buff);
+    }
 
-        for t in buff_iter {
-            let mut pops = stack.pop_range(|e| e.kind() == t.kind() && e.value() == t.value());
-            if !pops.is_empty() {
-                // found
-                let matched = pops.get_mut(0).unwrap(); // Notice: don't panic
-                match t.value() {
-                    "*" | "_" => {
-                        (matched.kind, t.kind) = (TokenKind::ItalicMark, TokenKind::ItalicMark);
-                    }
-                    "**" | "__" => {
-                        (matched.kind, t.kind) = (TokenKind::BoldMark, TokenKind::BoldMark);
-                    }
-                    "***" | "___" => {
-                        (matched.kind, t.kind) =
-                            (TokenKind::ItalicBoldMark, TokenKind::ItalicBoldMark);
-                    }
-                    "`" | "``" | "```" => {
-                        (matched.kind, t.kind) = (TokenKind::CodeMark, TokenKind::CodeMark);
-                    }
-                    "~~" => {
-                        (matched.kind, t.kind) = (TokenKind::DeleteMark, TokenKind::DeleteMark);
+    // Pair the backtick runs of the same length as the code spans, the marks in the code span
+    // are plain text. The backtick run without pair is plain text too.
+    fn tidy_code_spans(buff: &mut [Token]) {
+        let mut ix = 0;
+        while ix < buff.len() {
+            if buff[ix].kind() == TokenKind::BackTick {
+                let closing = (ix + 1..buff.len()).find(|j| {
+                    buff[*j].kind() == TokenKind::BackTick && buff[*j].value() == buff[ix].value()
+                });
+                match closing {
+                    Some(j) => {
+                        (buff[ix].kind, buff[j].kind) = (TokenKind::CodeMark, TokenKind::CodeMark);
+                        buff[j].closing = true;
+                        for t in buff[ix + 1..j].iter_mut() {
+                            if matches!(t.kind(), TokenKind::SoftBreak | TokenKind::LineBreak) {
+                                // the line break in the code span is a space
+                                *t = Token::new(" ".to_string(), TokenKind::Text);
+                            } else if t.is_delimiter_run() {
+                                t.kind = TokenKind::Text;
+                            }
+                        }
+                        ix = j;
                     }
-                    _ => unreachable!(),
-                }
-                pops.iter_mut()
-                    .skip(1)
-                    .for_each(|e| e.kind = TokenKind::Text);
-            } else {
-                // not found in stack
-                if t.len() < 4 {
-                    stack.push(t);
-                } else {
-                    t.kind = TokenKind::Text;
+                    None => buff[ix].kind = TokenKind::Text,
                 }
             }
+            ix += 1;
         }
-
-        // At last, we need to update the elment left on the stack
-        stack
-            .all_mut()
-            .iter_mut()
-            .for_each(|e| e.kind = TokenKind::Text);
     }
 
-    fn tidy_continuous_mark(kind: TokenKind, buff: &mut Vec<Token>) {
-        let mut splits_at: VecDeque<(usize, usize)> = VecDeque::new();
-
-        let mut pre: usize = 0;
-
-        for (ix, t) in buff.iter().enumerate().filter(|(_, t)| t.kind() == kind) {
-            let n = if t.len() >= pre && pre > 0 {
-                t.len() - pre
-            } else {
-                pre = t.len();
+    // Pair the delimiter runs of '*', '_' and '~' as the emphasis and strikethrough by the
+    // algorithm of CommonMark, the pairs are always nested properly. The characters of a run
+    // are consumed from the inner side, and the rest of them are plain text.
+    // .e.g "***a** b*" is "<em><strong>a</strong> b</em>", "a_b_c" is plain text.
+    fn tidy_delimiter_runs(buff: &mut Vec<Token>) {
+        let mut delimiters: Vec<Delimiter> = buff
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.is_delimiter_run())
+            .map(|(ix, t)| Delimiter::new(buff, ix, t))
+            .collect();
+
+        let mut closer = 0;
+        while closer < delimiters.len() {
+            let c = &delimiters[closer];
+            if !c.active || !c.can_close || c.remain == 0 {
+                closer += 1;
                 continue;
-            };
-            match n.cmp(&0) {
-                // need to split current token
-                Ordering::Greater => {
-                    let l = splits_at.len();
-                    splits_at.push_back((ix + l, pre));
-
-                    pre = n;
+            }
+            let opener = (0..closer).rev().find(|o| delimiters[*o].can_pair_with(c));
+            match opener {
+                Some(opener) => {
+                    let (o, c) = (&delimiters[opener], &delimiters[closer]);
+                    let n = if o.remain >= 2 && c.remain >= 2 { 2 } else { 1 };
+                    delimiters[opener].remain -= n;
+                    delimiters[opener].opens.push((closer, n));
+                    delimiters[closer].remain -= n;
+                    delimiters[closer].closes.push((opener, n));
+                    // the delimiters between the pair can't be paired anymore
+                    delimiters[opener + 1..closer]
+                        .iter_mut()
+                        .for_each(|d| d.active = false);
+                    if delimiters[closer].remain == 0 {
+                        closer += 1;
+                    }
                 }
-                // matched with previous token
-                Ordering::Equal => {
-                    pre = 0;
+                None => {
+                    if !c.can_open {
+                        delimiters[closer].active = false;
+                    }
+                    closer += 1;
                 }
-                Ordering::Less => unreachable!(),
             }
         }
-        splits_at.into_iter().for_each(|(ix, l)| {
-            let off = buff[ix].split_off(l);
-            buff.insert(ix + 1, off);
-        });
+
+        // replace every run with the marks and the rest characters
+        for d in delimiters.into_iter().rev() {
+            let mut tokens = d.marks(&d.closes, true);
+            if d.remain > 0 {
+                tokens.push(Token::new(
+                    d.ch.to_string().repeat(d.remain),
+                    TokenKind::Text,
+                ));
+            }
+            tokens.extend(d.marks(&d.opens, false).into_iter().rev());
+            buff.splice(d.ix..d.ix + 1, tokens);
+        }
     }
 
     fn goto(&mut self, state: State) {
@@ -613,6 +909,15 @@ impl<'lexer> Lexer<'lexer> {
         self.slice_str(begin, end).to_string()
     }
 
+    // Get the index of the '\n' at the end of the line which contains the position
+    fn line_end(content: &str, from: usize) -> usize {
+        content
+            .chars()
+            .skip(from)
+            .position(|c| c == '\n')
+            .map_or(content.chars().count(), |p| from + p)
+    }
+
     // find 'line break', double spaces or <br> at the end of the line
     fn has_br(s: &str) -> bool {
         if s.ends_with("  \n") {
@@ -622,6 +927,20 @@ impl<'lexer> Lexer<'lexer> {
         }
     }
 
+    // Remove the optional closing sequence of '#' in the title, .e.g "## title ##".
+    // The closing sequence must be preceded by a whitespace, otherwise it's a part of the title.
+    fn trim_title_closing(s: &str) -> String {
+        let trimmed = s.trim_end();
+        let content = trimmed.trim_end_matches('#');
+        if content.len() == trimmed.len() {
+            s.to_string()
+        } else if content.is_empty() || content.ends_with([' ', '\t']) {
+            content.trim_end().to_string() + "\n"
+        } else {
+            s.to_string()
+        }
+    }
+
     fn is_dividing(s: &str) -> bool {
         let counts = s.chars().filter(|c| !c.is_whitespace()).counts();
 
@@ -635,6 +954,11 @@ impl<'lexer> Lexer<'lexer> {
                 >= 3
     }
 
+    // The label of footnote starts with '^', and it doesn't contain any whitespace
+    fn is_footnote_label(s: &str) -> bool {
+        s.len() > 1 && s.starts_with('^') && !s.contains(char::is_whitespace)
+    }
+
     fn is_todolist(s: &str) -> Option<(&str, TokenKind)> {
         let s = s.trim_start();
         match s.find("] ").or_else(|| s.find("]\t")) {
@@ -658,11 +982,113 @@ impl<'lexer> Lexer<'lexer> {
     }
 }
 
+// Delimiter is a run of '*', '_' or '~' in the line, it may open or close the emphasis and
+// strikethrough.
+struct Delimiter {
+    // the index of the token in the line
+    ix: usize,
+    ch: char,
+    len: usize,
+    // the number of characters that are not paired yet
+    remain: usize,
+    can_open: bool,
+    can_close: bool,
+    // the delimiter can't be paired if it's false
+    active: bool,
+    // the pairs of (index of the other delimiter, number of characters), from inner to outer
+    opens: Vec<(usize, usize)>,
+    closes: Vec<(usize, usize)>,
+}
+
+impl Delimiter {
+    fn new(buff: &[Token], ix: usize, t: &Token) -> Self {
+        let ch = t.value().chars().next().unwrap_or_default();
+        // the beginning and end of the line are treated as whitespaces
+        let before = ix
+            .checked_sub(1)
+            .map(|i| &buff[i])
+            .filter(|t| t.kind() != TokenKind::LineBreak)
+            .and_then(|t| t.value().chars().last())
+            .unwrap_or(' ');
+        let after = buff
+            .get(ix + 1)
+            .filter(|t| t.kind() != TokenKind::LineBreak)
+            .and_then(|t| t.value().chars().next())
+            .unwrap_or(' ');
+
+        let is_punctuation = |c: char| !c.is_alphanumeric() && !c.is_whitespace();
+        let left_flanking = !after.is_whitespace()
+            && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+        let right_flanking = !before.is_whitespace()
+            && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+
+        // the '_' can't open or close the emphasis inside a word, .e.g "snake_case_word"
+        let (can_open, can_close) = if ch == '_' {
+            (
+                left_flanking && (!right_flanking || is_punctuation(before)),
+                right_flanking && (!left_flanking || is_punctuation(after)),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+
+        Delimiter {
+            ix,
+            ch,
+            len: t.len(),
+            remain: t.len(),
+            can_open,
+            can_close,
+            active: true,
+            opens: vec![],
+            closes: vec![],
+        }
+    }
+
+    // Determine whether the delimiter can open the emphasis which is closed by the closer
+    fn can_pair_with(&self, closer: &Delimiter) -> bool {
+        if !self.active || !self.can_open || self.remain == 0 || self.ch != closer.ch {
+            return false;
+        }
+        // the strikethrough must be "~~"
+        if self.ch == '~' {
+            return self.len == 2 && closer.len == 2;
+        }
+        // the rule of 3, .e.g "*a**b*" is "<em>a**b</em>"
+        !((self.can_close || closer.can_open)
+            && (self.len + closer.len).is_multiple_of(3)
+            && !(self.len.is_multiple_of(3) && closer.len.is_multiple_of(3)))
+    }
+
+    // Convert the pairs into the mark tokens, from inner to outer. The bold and italic paired
+    // with the same delimiter are merged, .e.g "***a***".
+    fn marks(&self, pairs: &[(usize, usize)], closing: bool) -> Vec<Token> {
+        let mut tokens = vec![];
+        let mut iter = pairs.iter().peekable();
+        while let Some((other, n)) = iter.next() {
+            let n = match iter.next_if(|(o, m)| o == other && *n == 2 && *m == 1) {
+                Some(_) => 3,
+                None => *n,
+            };
+            let kind = match (self.ch, n) {
+                ('~', _) => TokenKind::DeleteMark,
+                (_, 1) => TokenKind::ItalicMark,
+                (_, 2) => TokenKind::BoldMark,
+                _ => TokenKind::ItalicBoldMark,
+            };
+            let mut t = Token::new(self.ch.to_string().repeat(n), kind);
+            t.closing = closing;
+            tokens.push(t);
+        }
+        tokens
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub(crate) enum TokenKind {
     TitleMark,      // #, ##, ###, ####
     UnorderedMark,  // *, -, +
-    OrderedMark,    // 1. 2. 3. ...
+    OrderedMark,    // 1. 2. 3. ... or 1) 2) 3) ...
     TodoDoneMark,   // * [x], - [x], + [x]
     TodoUndoneMark, // * [ ], - [ ], + [ ]
     DividingMark,   // ---, ***, ___
@@ -670,16 +1096,23 @@ pub(crate) enum TokenKind {
     BoldMark,       // ** **
     ItalicMark,     // * *
     ItalicBoldMark, // *** ***
-    CodeBlockMark,  // ```
+    CodeBlockMark,  // ```, ~~~
     CodeMark,       // `
     BlankLine,      // \n
     LineBreak,      // <br>, double whitespace
+    SoftBreak,      // \n between the lines of paragraph
     Image,          // ![name](location "title")
     Link,           // [name](location "title")
     QuickLink,      // <url or email>
-    RefLink,        // [name][tag]
+    RefLink,        // [name][tag], [tag][], [tag]
+    RefImage,       // ![name][tag], ![tag][], ![tag]
     RefLinkDef,     // [tag]: link "title"
+    FootnoteRef,    // [^label]
+    FootnoteDef,    // [^label]: text
     DeleteMark,     // ~~
+    TableCellMark,  // |
+    Math,           // $x$, $$x$$
+    Html,           // <span>, </span>, <!-- -->
     Text,           //
     Star,           // *
     UnderLine,      // _
@@ -695,6 +1128,8 @@ pub(crate) struct Token {
     kind: TokenKind,
     second_kind: Option<TokenKind>,
     fields: Option<HashMap<String, String>>,
+    // The mark closes a pair, .e.g the second "**" in "**bold**"
+    closing: bool,
 }
 
 impl Token {
@@ -704,6 +1139,7 @@ impl Token {
             kind,
             second_kind: None,
             fields: None,
+            closing: false,
         }
     }
 
@@ -731,50 +1167,60 @@ impl Token {
         self.second_kind
     }
 
+    // Determine whether the mark closes a pair, the pairs of marks are always nested properly
+    pub(crate) fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    // Determine whether the token is a run of '*', '_', '`' or '~' that is not paired yet
+    fn is_delimiter_run(&self) -> bool {
+        matches!(
+            self.kind(),
+            TokenKind::Star | TokenKind::UnderLine | TokenKind::BackTick | TokenKind::Tilde
+        )
+    }
+
     // Check if the token is empty
     pub(crate) fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
     // convert the token to generic link token
-    pub(crate) fn as_generic_link(&self) -> GenericLinkToken {
+    pub(crate) fn as_generic_link(&self) -> GenericLinkToken<'_> {
         if self.kind() != TokenKind::Link
             && self.kind() != TokenKind::Image
             && self.kind() != TokenKind::RefLink
+            && self.kind() != TokenKind::RefImage
             && self.kind() != TokenKind::RefLinkDef
             && self.kind() != TokenKind::QuickLink
+            && self.kind() != TokenKind::FootnoteRef
+            && self.kind() != TokenKind::FootnoteDef
         {
             panic!("token is not a generic link");
         }
         GenericLinkToken(self)
     }
 
-    pub(crate) fn as_generic_link_mut(&mut self) -> GenericLinkTokenAsMut {
+    pub(crate) fn as_generic_link_mut(&mut self) -> GenericLinkTokenAsMut<'_> {
         if self.kind() != TokenKind::Link
             && self.kind() != TokenKind::Image
             && self.kind() != TokenKind::RefLink
+            && self.kind() != TokenKind::RefImage
             && self.kind() != TokenKind::RefLinkDef
             && self.kind() != TokenKind::QuickLink
+            && self.kind() != TokenKind::FootnoteRef
+            && self.kind() != TokenKind::FootnoteDef
         {
             panic!("token is not a generic link");
         }
         GenericLinkTokenAsMut(self)
     }
 
-    fn split_off(&mut self, at: usize) -> Token {
-        let off = self.value.split_off(at);
-        Token::new(off, self.kind())
-    }
-
     fn insert(&mut self, k: &str, v: &str) {
         self.fields
             .get_or_insert(HashMap::new())
             .insert(k.to_string(), v.to_string());
     }
-
-    pub(crate) fn downgrade_to_text(&mut self) {
-        self.kind = TokenKind::Text
-    }
 }
 
 // Link Token
@@ -793,8 +1239,7 @@ impl<'generic_link_token> GenericLinkToken<'generic_link_token> {
 
     // Convert name to tokens
     pub(crate) fn name_to_tokens(&self) -> Vec<Token> {
-        let name = self.name().to_string() + "\n";
-        Lexer::new(name.as_str()).split()
+        Lexer::new(self.name()).split_inline_only()
     }
 
     // Get location of the link
@@ -842,13 +1287,16 @@ impl<'generic_link_token_as_mut> GenericLinkTokenAsMut<'generic_link_token_as_mu
         }
     }
 
+    // Insert the title without the surrounding quotes, the escaped quotes in it are unescaped
     pub(crate) fn insert_title(&mut self, v: &str) {
+        let v = match v.chars().next() {
+            Some(q @ ('"' | '\'')) if v.len() > 1 && v.ends_with(q) => {
+                v[1..v.len() - 1].replace(&format!("\\{}", q), &q.to_string())
+            }
+            _ => v.to_string(),
+        };
         if !v.is_empty() {
-            self.0.insert(
-                "title",
-                v.trim_end_matches(['"', '\''])
-                    .trim_start_matches(['"', '\'']),
-            )
+            self.0.insert("title", &v)
         }
     }
 
@@ -863,6 +1311,13 @@ impl<'generic_link_token_as_mut> GenericLinkTokenAsMut<'generic_link_token_as_mu
 mod tests {
     use super::*;
 
+    // Split the line into tokens, the direction of the marks is ignored
+    fn split(s: &str) -> Vec<Token> {
+        let mut tokens = Lexer::new(s).split();
+        tokens.iter_mut().for_each(|t| t.closing = false);
+        tokens
+    }
+
     fn exec_cases(cases: Vec<(&str, Vec<(&str, TokenKind)>)>) {
         for c in cases.iter() {
             let s = if c.0.ends_with('\n') {
@@ -874,7 +1329,7 @@ mod tests {
             };
 
             assert_eq!(
-                Lexer::new(s.as_str()).split(),
+                split(s.as_str()),
                 c.1.iter()
                     .map(|(v, k)| { Token::new(v.to_string(), *k) })
                     .collect::<Vec<Token>>()
@@ -882,7 +1337,10 @@ mod tests {
         }
     }
 
-    fn exec_cases_with_second_kind(cases: Vec<(&str, Vec<(&str, TokenKind, Option<TokenKind>)>)>) {
+    type SecondKindCase<'a> = (&'a str, TokenKind, Option<TokenKind>);
+    type GenericLinkCase<'a> = (&'a str, TokenKind, &'a str, &'a str, &'a str);
+
+    fn exec_cases_with_second_kind(cases: Vec<(&str, Vec<SecondKindCase>)>) {
         for c in cases.iter() {
             let s = if c.0.ends_with('\n') {
                 c.0.to_string()
@@ -893,7 +1351,7 @@ mod tests {
             };
 
             assert_eq!(
-                Lexer::new(s.as_str()).split(),
+                split(s.as_str()),
                 c.1.iter()
                     .map(|(v, k, k2)| {
                         let mut t = Token::new(v.to_string(), *k);
@@ -905,7 +1363,7 @@ mod tests {
         }
     }
 
-    fn exec_generic_link_cases(cases: Vec<(&str, Vec<(&str, TokenKind, &str, &str, &str)>)>) {
+    fn exec_generic_link_cases(cases: Vec<(&str, Vec<GenericLinkCase>)>) {
         for c in cases.iter() {
             let s = if c.0.ends_with('\n') {
                 c.0.to_string()
@@ -916,7 +1374,7 @@ mod tests {
             };
 
             assert_eq!(
-                Lexer::new(s.as_str()).split(),
+                split(s.as_str()),
                 c.1.iter()
                     .map(|(v, k, s1, s2, s3)| {
                         let mut t = Token::new(v.to_string(), *k);
@@ -927,7 +1385,7 @@ mod tests {
                                 tm.insert_location(s2);
                                 tm.insert_title(s3);
                             }
-                            TokenKind::RefLink => {
+                            TokenKind::RefLink | TokenKind::RefImage => {
                                 let mut tm = t.as_generic_link_mut();
                                 tm.insert_name(s1);
                                 tm.insert_reflink_tag(s2)
@@ -938,6 +1396,14 @@ mod tests {
                                 tm.insert_location(s2);
                                 tm.insert_title(s3);
                             }
+                            TokenKind::FootnoteRef => {
+                                t.as_generic_link_mut().insert_reflink_tag(s1);
+                            }
+                            TokenKind::FootnoteDef => {
+                                let mut tm = t.as_generic_link_mut();
+                                tm.insert_reflink_tag(s1);
+                                tm.insert_name(s2);
+                            }
                             _ => (),
                         }
                         t
@@ -963,8 +1429,12 @@ mod tests {
             ),
             ("#这不是标题", vec![("#这不是标题", TokenKind::Text)]),
             ("##这也不是标题", vec![("##这也不是标题", TokenKind::Text)]),
-            (">这不是引用", vec![(">这不是引用", TokenKind::Text)]),
             ("1.这也不是列表", vec![("1.这也不是列表", TokenKind::Text)]),
+            ("z", vec![("z", TokenKind::Text)]),
+            (
+                "  z",
+                vec![("  ", TokenKind::WhiteSpace), ("z", TokenKind::Text)],
+            ),
             (
                 "***xxxx",
                 vec![("***", TokenKind::Text), ("xxxx", TokenKind::Text)],
@@ -1005,6 +1475,25 @@ mod tests {
         exec_cases(cases);
     }
 
+    #[test]
+    fn test_emphasis_closing() {
+        let cases = vec![
+            ("****a****", vec![false, false, false, true, true]),
+            (
+                "*a **b* c**",
+                vec![false, false, false, false, false, true, false, true, true],
+            ),
+            ("`*a*`", vec![false, false, false, false, true]),
+        ];
+        for (s, closing) in cases {
+            let tokens = Lexer::new(&format!("{}\n", s)).split();
+            assert_eq!(
+                tokens.iter().map(|t| t.is_closing()).collect::<Vec<bool>>(),
+                closing
+            );
+        }
+    }
+
     #[test]
     fn test_bold_italic() {
         let cases = vec![
@@ -1029,9 +1518,64 @@ mod tests {
                     ("1", TokenKind::Text),
                     ("**", TokenKind::BoldMark),
                     (" ", TokenKind::Text),
-                    ("****", TokenKind::Text),
+                    ("*", TokenKind::Text),
+                    ("***", TokenKind::ItalicBoldMark),
                     ("2", TokenKind::Text),
-                    ("***", TokenKind::Text),
+                    ("***", TokenKind::ItalicBoldMark),
+                ],
+            ),
+            (
+                "***a** b*",
+                vec![
+                    ("*", TokenKind::ItalicMark),
+                    ("**", TokenKind::BoldMark),
+                    ("a", TokenKind::Text),
+                    ("**", TokenKind::BoldMark),
+                    (" b", TokenKind::Text),
+                    ("*", TokenKind::ItalicMark),
+                ],
+            ),
+            (
+                "*a **b* c**",
+                vec![
+                    ("*", TokenKind::ItalicMark),
+                    ("a ", TokenKind::Text),
+                    ("*", TokenKind::ItalicMark),
+                    ("*", TokenKind::ItalicMark),
+                    ("b", TokenKind::Text),
+                    ("*", TokenKind::ItalicMark),
+                    (" c", TokenKind::Text),
+                    ("*", TokenKind::ItalicMark),
+                    ("*", TokenKind::ItalicMark),
+                ],
+            ),
+            (
+                "snake_case_word _a_ * b*",
+                vec![
+                    ("snake", TokenKind::Text),
+                    ("_", TokenKind::Text),
+                    ("case", TokenKind::Text),
+                    ("_", TokenKind::Text),
+                    ("word ", TokenKind::Text),
+                    ("_", TokenKind::ItalicMark),
+                    ("a", TokenKind::Text),
+                    ("_", TokenKind::ItalicMark),
+                    (" ", TokenKind::Text),
+                    ("*", TokenKind::Text),
+                    (" b", TokenKind::Text),
+                    ("*", TokenKind::Text),
+                ],
+            ),
+            (
+                "~~a *b~~ c*",
+                vec![
+                    ("~~", TokenKind::DeleteMark),
+                    ("a ", TokenKind::Text),
+                    ("*", TokenKind::Text),
+                    ("b", TokenKind::Text),
+                    ("~~", TokenKind::DeleteMark),
+                    (" c", TokenKind::Text),
+                    ("*", TokenKind::Text),
                 ],
             ),
             (
@@ -1063,9 +1607,9 @@ mod tests {
             (
                 "*** 1 ***",
                 vec![
-                    ("***", TokenKind::ItalicBoldMark),
+                    ("***", TokenKind::Text),
                     (" 1 ", TokenKind::Text),
-                    ("***", TokenKind::ItalicBoldMark),
+                    ("***", TokenKind::Text),
                 ],
             ),
             (
@@ -1087,9 +1631,9 @@ mod tests {
             (
                 "___ 1 ___",
                 vec![
-                    ("___", TokenKind::ItalicBoldMark),
+                    ("___", TokenKind::Text),
                     (" 1 ", TokenKind::Text),
-                    ("___", TokenKind::ItalicBoldMark),
+                    ("___", TokenKind::Text),
                 ],
             ),
             (
@@ -1132,6 +1676,60 @@ mod tests {
         exec_cases(cases);
     }
 
+    #[test]
+    fn test_paragraph() {
+        let cases = vec![
+            (
+                "first\nsecond  \nthird<br>\nlast",
+                vec![
+                    ("first", TokenKind::Text),
+                    ("\n", TokenKind::SoftBreak),
+                    ("second", TokenKind::Text),
+                    ("<br>", TokenKind::LineBreak),
+                    ("third", TokenKind::Text),
+                    ("<br>", TokenKind::LineBreak),
+                    ("last", TokenKind::Text),
+                ],
+            ),
+            (
+                "a **b\nc** `d\ne`",
+                vec![
+                    ("a ", TokenKind::Text),
+                    ("**", TokenKind::BoldMark),
+                    ("b", TokenKind::Text),
+                    ("\n", TokenKind::SoftBreak),
+                    ("c", TokenKind::Text),
+                    ("**", TokenKind::BoldMark),
+                    (" ", TokenKind::Text),
+                    ("`", TokenKind::CodeMark),
+                    ("d", TokenKind::Text),
+                    (" ", TokenKind::Text),
+                    ("e", TokenKind::Text),
+                    ("`", TokenKind::CodeMark),
+                ],
+            ),
+        ];
+        for (s, expected) in cases {
+            let mut tokens = Lexer::new(s).split_inline_only();
+            tokens.iter_mut().for_each(|t| t.closing = false);
+            assert_eq!(
+                tokens,
+                expected
+                    .iter()
+                    .map(|(v, k)| Token::new(v.to_string(), *k))
+                    .collect::<Vec<Token>>()
+            );
+        }
+
+        let tokens = Lexer::new("[long\nname](url \"a\ntitle\")").split_inline_only();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].kind(), TokenKind::Link);
+        let link = tokens[0].as_generic_link();
+        assert_eq!(link.name(), "long\nname");
+        assert_eq!(link.location(), "url");
+        assert_eq!(link.title(), "a\ntitle");
+    }
+
     #[test]
     fn test_blank_line() {
         let cases = vec![
@@ -1351,6 +1949,23 @@ mod tests {
             ("[]]()", vec![("[]]()", TokenKind::Link, "]", "", "")]),
             ("[]]]]()", vec![("[]]]]()", TokenKind::Link, "]]]", "", "")]),
             ("[!]]()", vec![("[!]]()", TokenKind::Link, "!]", "", "")]),
+            (
+                "[x](javascript:alert(1))",
+                vec![(
+                    "[x](javascript:alert(1))",
+                    TokenKind::Link,
+                    "x",
+                    "javascript:alert(1)",
+                    "",
+                )],
+            ),
+            (
+                "[a](/a_(b) \"t (\").",
+                vec![
+                    ("[a](/a_(b) \"t (\")", TokenKind::Link, "a", "/a_(b)", "t ("),
+                    (".", TokenKind::Text, "", "", ""),
+                ],
+            ),
         ];
 
         exec_generic_link_cases(cases);
@@ -1383,6 +1998,27 @@ mod tests {
             ),
             ("# ", vec![("#", TokenKind::TitleMark)]),
             ("#  ", vec![("#", TokenKind::TitleMark)]),
+            (
+                "## header2 ##",
+                vec![("##", TokenKind::TitleMark), ("header2", TokenKind::Text)],
+            ),
+            (
+                "# header1 #####   ",
+                vec![("#", TokenKind::TitleMark), ("header1", TokenKind::Text)],
+            ),
+            (
+                "# header1#",
+                vec![("#", TokenKind::TitleMark), ("header1#", TokenKind::Text)],
+            ),
+            (
+                "# header1 \\#",
+                vec![
+                    ("#", TokenKind::TitleMark),
+                    ("header1 ", TokenKind::Text),
+                    ("#", TokenKind::Text),
+                ],
+            ),
+            ("### ###", vec![("###", TokenKind::TitleMark)]),
         ];
         exec_cases(cases);
     }
@@ -1401,6 +2037,12 @@ mod tests {
                 ">> Rust\n",
                 TokenKind::Text,
             )],
+        ), (
+            ">Rust\n",
+            vec![(">", TokenKind::QuoteMark), ("Rust\n", TokenKind::Text)],
+        ), (
+            ">\n",
+            vec![(">", TokenKind::QuoteMark)],
         )];
         exec_cases(cases);
     }
@@ -1445,6 +2087,24 @@ mod tests {
                 "999. rust",
                 vec![("999.", TokenKind::OrderedMark), ("rust", TokenKind::Text)],
             ),
+            (
+                "1000. rust",
+                vec![("1000.", TokenKind::OrderedMark), ("rust", TokenKind::Text)],
+            ),
+            (
+                "1) rust",
+                vec![("1)", TokenKind::OrderedMark), ("rust", TokenKind::Text)],
+            ),
+            (
+                "007) rust",
+                vec![("007)", TokenKind::OrderedMark), ("rust", TokenKind::Text)],
+            ),
+            (
+                "1234567890. rust",
+                vec![("1234567890. rust", TokenKind::Text)],
+            ),
+            ("1a. rust", vec![("1a. rust", TokenKind::Text)]),
+            (". rust", vec![(". rust", TokenKind::Text)]),
         ];
         exec_cases(cases);
     }
@@ -1457,6 +2117,28 @@ mod tests {
                 "```rust",
                 vec![("```", TokenKind::CodeBlockMark), ("rust", TokenKind::Text)],
             ),
+            (
+                "~~~~ rust",
+                vec![
+                    ("~~~~", TokenKind::CodeBlockMark),
+                    (" rust", TokenKind::Text),
+                ],
+            ),
+            (
+                "   ````",
+                vec![
+                    ("   ", TokenKind::WhiteSpace),
+                    ("````", TokenKind::CodeBlockMark),
+                ],
+            ),
+            (
+                "``` rust`",
+                vec![
+                    ("```", TokenKind::Text),
+                    (" rust", TokenKind::Text),
+                    ("`", TokenKind::Text),
+                ],
+            ),
         ];
         exec_cases(cases);
     }
@@ -1532,6 +2214,46 @@ mod tests {
                     (".", TokenKind::Text, "", "", ""),
                 ],
             ),
+            (
+                "![Logo][logo] [Foo Bar][] and [foo bar].",
+                vec![
+                    ("![Logo][logo]", TokenKind::RefImage, "Logo", "logo", ""),
+                    (" ", TokenKind::Text, "", "", ""),
+                    ("[Foo Bar][]", TokenKind::RefLink, "Foo Bar", "Foo Bar", ""),
+                    (" and ", TokenKind::Text, "", "", ""),
+                    ("[foo bar]", TokenKind::RefLink, "foo bar", "foo bar", ""),
+                    (".", TokenKind::Text, "", "", ""),
+                ],
+            ),
+            (
+                "![logo] [*a*]]",
+                vec![
+                    ("![logo]", TokenKind::RefImage, "logo", "logo", ""),
+                    (" ", TokenKind::Text, "", "", ""),
+                    ("[*a*]", TokenKind::RefLink, "*a*", "*a*", ""),
+                    ("]", TokenKind::Text, "", "", ""),
+                ],
+            ),
+            (
+                "![example]exmaple.com**example**",
+                vec![
+                    ("![example]", TokenKind::RefImage, "example", "example", ""),
+                    ("exmaple.com", TokenKind::Text, "", "", ""),
+                    ("**", TokenKind::BoldMark, "", "", ""),
+                    ("example", TokenKind::Text, "", "", ""),
+                    ("**", TokenKind::BoldMark, "", "", ""),
+                ],
+            ),
+            (
+                "[example]exmaple.com**example**",
+                vec![
+                    ("[example]", TokenKind::RefLink, "example", "example", ""),
+                    ("exmaple.com", TokenKind::Text, "", "", ""),
+                    ("**", TokenKind::BoldMark, "", "", ""),
+                    ("example", TokenKind::Text, "", "", ""),
+                    ("**", TokenKind::BoldMark, "", "", ""),
+                ],
+            ),
         ];
         exec_generic_link_cases(cases);
     }
@@ -1559,26 +2281,124 @@ mod tests {
                     "example",
                 )],
             ),
+            (
+                "[link]: https://example.com 'it\\'s'",
+                vec![(
+                    "[link]: https://example.com 'it\\'s'",
+                    TokenKind::RefLinkDef,
+                    "link",
+                    "https://example.com",
+                    "it's",
+                )],
+            ),
         ];
         exec_generic_link_cases(cases);
     }
 
     #[test]
-    fn test_inline_fallback() {
+    fn test_footnote() {
         let cases = vec![
             (
-                "![example](exmaple.com**example**",
+                "text[^1].",
                 vec![
-                    ("![example](exmaple.com", TokenKind::Text),
-                    ("**", TokenKind::BoldMark),
-                    ("example", TokenKind::Text),
-                    ("**", TokenKind::BoldMark),
+                    ("text", TokenKind::Text, "", "", ""),
+                    ("[^1]", TokenKind::FootnoteRef, "1", "", ""),
+                    (".", TokenKind::Text, "", "", ""),
                 ],
             ),
             (
-                "![example]exmaple.com**example**",
+                "[^note]: This is a **footnote**.",
+                vec![(
+                    "[^note]: This is a **footnote**.",
+                    TokenKind::FootnoteDef,
+                    "note",
+                    "This is a **footnote**.",
+                    "",
+                )],
+            ),
+            (
+                "see [^1]: here",
+                vec![
+                    ("see ", TokenKind::Text, "", "", ""),
+                    ("[^1]", TokenKind::FootnoteRef, "1", "", ""),
+                    (": here", TokenKind::Text, "", "", ""),
+                ],
+            ),
+            ("[^ 1]", vec![("[^ 1]", TokenKind::Text, "", "", "")]),
+        ];
+        exec_generic_link_cases(cases);
+    }
+
+    #[test]
+    fn test_math() {
+        let cases = vec![
+            (
+                "energy $E = mc^2$.",
                 vec![
-                    ("![example]exmaple.com", TokenKind::Text),
+                    ("energy ", TokenKind::Text),
+                    ("$E = mc^2$", TokenKind::Math),
+                    (".", TokenKind::Text),
+                ],
+            ),
+            ("$$a_1 * b_1$$", vec![("$$a_1 * b_1$$", TokenKind::Math)]),
+            ("$5 and $6", vec![("$5 and $6", TokenKind::Text)]),
+            ("$ x $", vec![("$ x $", TokenKind::Text)]),
+            ("\\$x$", vec![("$x$", TokenKind::Text)]),
+            (
+                "`$x$` $y$",
+                vec![
+                    ("`", TokenKind::CodeMark),
+                    ("$x$", TokenKind::Text),
+                    ("`", TokenKind::CodeMark),
+                    (" ", TokenKind::Text),
+                    ("$y$", TokenKind::Math),
+                ],
+            ),
+        ];
+        exec_cases(cases);
+    }
+
+    #[test]
+    fn test_inline_html() {
+        let cases = vec![
+            (
+                "a <span class=\"x\">b</span> c",
+                vec![
+                    ("a ", TokenKind::Text),
+                    ("<span class=\"x\">", TokenKind::Html),
+                    ("b", TokenKind::Text),
+                    ("</span>", TokenKind::Html),
+                    (" c", TokenKind::Text),
+                ],
+            ),
+            (
+                "<!-- comment --><br/>",
+                vec![
+                    ("<!-- comment -->", TokenKind::Html),
+                    ("<br/>", TokenKind::Html),
+                ],
+            ),
+            (
+                "`<b>` 1 < 2",
+                vec![
+                    ("`", TokenKind::CodeMark),
+                    ("<b>", TokenKind::Text),
+                    ("`", TokenKind::CodeMark),
+                    (" 1 < 2", TokenKind::Text),
+                ],
+            ),
+            ("<a href=\"x>", vec![("<a href=\"x>", TokenKind::Text)]),
+        ];
+        exec_cases(cases);
+    }
+
+    #[test]
+    fn test_inline_fallback() {
+        let cases = vec![
+            (
+                "![example](exmaple.com**example**",
+                vec![
+                    ("![example](exmaple.com", TokenKind::Text),
                     ("**", TokenKind::BoldMark),
                     ("example", TokenKind::Text),
                     ("**", TokenKind::BoldMark),
@@ -1611,15 +2431,6 @@ mod tests {
                     ("**", TokenKind::BoldMark),
                 ],
             ),
-            (
-                "[example]exmaple.com**example**",
-                vec![
-                    ("[example]exmaple.com", TokenKind::Text),
-                    ("**", TokenKind::BoldMark),
-                    ("example", TokenKind::Text),
-                    ("**", TokenKind::BoldMark),
-                ],
-            ),
             (
                 "[example][exmaple.com**example**",
                 vec![
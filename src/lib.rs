@@ -1,6 +1,8 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+mod highlight;
 mod html;
 mod lexer;
 pub mod markdown;
@@ -9,6 +11,9 @@ pub mod utils;
 
 pub type SharedLine = Rc<RefCell<parser::Line>>;
 
+// The metadata of the document, it comes from the front matter (yaml or toml)
+pub type Metadata = HashMap<String, serde_json::Value>;
+
 pub trait Generate {
     fn render_title(&self, l: &SharedLine) -> String {
         l.borrow().text().trim().to_string()
@@ -22,6 +27,11 @@ pub trait Generate {
         "".to_string()
     }
 
+    // Render the paragraph in the item of a tight list, it's not wrapped in "<p>"
+    fn render_tight_text(&self, _ls: &[SharedLine]) -> String {
+        "".to_string()
+    }
+
     fn render_blank(&self, _ls: &[SharedLine]) -> String {
         "".to_string()
     }
@@ -34,11 +44,73 @@ pub trait Generate {
         "".to_string()
     }
 
+    // Render the definition list, the lines are the terms, descriptions and blank lines between
+    // them, .e.g "term\n: description"
+    fn render_definition_list(&self, _ls: &[SharedLine]) -> String {
+        "".to_string()
+    }
+
     fn render_quote(&self, _s: &str) -> String {
         "".to_string()
     }
 
+    // Render the callout that begins with "[!KIND]" in the quote, .e.g "> [!NOTE]". The 'kind' is
+    // lowercase, the 'title' is the custom title or the capitalized kind, .e.g "Note".
+    fn render_callout(&self, _kind: &str, _title: &str, s: &str) -> String {
+        self.render_quote(s)
+    }
+
+    // Render the container that is wrapped in the fences of ":::", .e.g "::: details Click me". The
+    // 'name' is lowercase, the 'title' is the text after the name and the 'attrs' are in the
+    // braces at the end, .e.g "{#id .class}". The unknown names are rendered here as well, so
+    // the generators can define their own containers.
+    fn render_container(
+        &self,
+        _name: &str,
+        _title: &str,
+        _attrs: &[(String, String)],
+        s: &str,
+    ) -> String {
+        s.to_string()
+    }
+
+    // Render the columns laid out horizontally, every column is the ratio of its width and the
+    // rendered content, .e.g [(1, "left"), (2, "right")]. The 'attrs' are the attributes of the
+    // columns container, .e.g [("id", "intro")] of "::: columns {#intro}"
+    fn render_columns(&self, _attrs: &[(String, String)], columns: &[(u32, String)]) -> String {
+        columns
+            .iter()
+            .map(|(_, s)| s.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n\n")
+    }
+
     fn render_code(&self, _ls: &[SharedLine]) -> String {
         "".to_string()
     }
+
+    fn render_table(&self, _ls: &[SharedLine]) -> String {
+        "".to_string()
+    }
+
+    // Render the raw html block, the lines are output as they are
+    fn render_raw_html(&self, _ls: &[SharedLine]) -> String {
+        "".to_string()
+    }
+
+    // Render the whole contents of the document or a slice, the blocks are already rendered and
+    // joined, .e.g the raw html blocks are sanitized with the contents between them
+    fn render_contents(&self, s: String) -> String {
+        s
+    }
+
+    // Render the math block, the lines include the opening and closing marks ("$$")
+    fn render_math(&self, _ls: &[SharedLine]) -> String {
+        "".to_string()
+    }
+
+    // Render the footnotes referenced since the last call
+    fn render_footnotes(&self) -> String {
+        "".to_string()
+    }
 }
@@ -1,7 +1,212 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 
 use crate::html;
 use crate::parser::Ast;
+use crate::Metadata;
+
+use serde::{Deserialize, Serialize};
+
+// The options of converting markdown into html
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HtmlOptions {
+    // The theme of the server-side syntax highlighting, .e.g "InspiredGitHub", "base16-ocean.dark".
+    // The code blocks are not highlighted if it's None.
+    pub highlight_theme: Option<String>,
+    // The policy to sanitize the raw html in the document, the raw html is output as it is if
+    // it's None, but it's sanitized by the default policy in the safe mode.
+    pub sanitize: Option<SanitizeOptions>,
+    // The safe mode percent-encodes the urls of the links and images, and drops the urls with the
+    // schemes that are not allowed, .e.g "javascript:", "vbscript:" and "data:" (except images).
+    // The raw html is always sanitized in the safe mode, and its urls have the same schemes.
+    pub safe: bool,
+    // The way to render the line breaks inside the paragraph that are not hard breaks
+    pub soft_break: SoftBreak,
+    // The options of the toc generated by 'to_toc_with' and 'to_body_toc_with', the toc at the
+    // "<!-- toc -->" position in the document uses the options of the marker.
+    pub toc: TocOptions,
+}
+
+// The options of the table of contents, the marker in the document specifies them as well,
+// .e.g "<!-- toc min=2 max=3 ordered numbering exclude=no-toc -->"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TocOptions {
+    // The range of the title levels in the toc, .e.g 2 and 3 only include "##" and "###"
+    pub min_level: usize,
+    pub max_level: usize,
+    // Output the ordered lists rather than the unordered lists
+    pub ordered: bool,
+    // Prefix the section numbers to the titles, .e.g "1.2 Install"
+    pub numbering: bool,
+    // The titles with the class are excluded, .e.g "## Changelog {.no-toc}"
+    pub exclude: String,
+}
+
+impl Default for TocOptions {
+    fn default() -> Self {
+        TocOptions {
+            min_level: 1,
+            max_level: 6,
+            ordered: false,
+            numbering: false,
+            exclude: "no-toc".to_string(),
+        }
+    }
+}
+
+impl TocOptions {
+    // Parse the options from the marker of the toc, the options are separated by white space
+    // after "toc". It's not a marker if any option is invalid, .e.g "<!-- toc is here -->" or
+    // "<!-- toc min=3 max=2 -->".
+    pub(crate) fn from_marker(s: &str) -> Option<Self> {
+        let s = s.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+        let mut words = s.split_whitespace();
+        if !words.next()?.eq_ignore_ascii_case("toc") {
+            return None;
+        }
+        let mut opts = TocOptions::default();
+        for w in words {
+            let level = |v: &str| v.parse::<usize>().ok().filter(|n| (1..=6).contains(n));
+            match w.split_once('=') {
+                None if w == "ordered" => opts.ordered = true,
+                None if w == "numbering" => opts.numbering = true,
+                Some(("min", v)) => opts.min_level = level(v)?,
+                Some(("max", v)) => opts.max_level = level(v)?,
+                Some(("exclude", v)) if !v.is_empty() => opts.exclude = v.to_string(),
+                _ => return None,
+            }
+        }
+        (opts.min_level <= opts.max_level).then_some(opts)
+    }
+}
+
+// The rendering of the soft line breaks, .e.g the hard-wrapped lines of a paragraph
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftBreak {
+    // Output a newline, the browser shows it as a space (CommonMark)
+    Newline,
+    // Output a "<br>", every line of the source is a line of the output
+    #[default]
+    Br,
+    // Join the lines without a space between two CJK characters, otherwise output a newline
+    Cjk,
+}
+
+// The allowlist of the raw html, the tags and attributes not in the list are removed.
+// The content of <script> and <style> is always removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizeOptions {
+    // The allowed tags, .e.g ["div", "span"]
+    pub tags: Vec<String>,
+    // The allowed attributes on all tags, .e.g ["class", "title"]
+    pub generic_attributes: Vec<String>,
+    // The allowed attributes on the specified tags, .e.g {"a": ["href"]}
+    pub tag_attributes: HashMap<String, Vec<String>>,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        let tags = [
+            "a",
+            "abbr",
+            "b",
+            "blockquote",
+            "br",
+            "caption",
+            "cite",
+            "code",
+            "dd",
+            "del",
+            "details",
+            "div",
+            "dl",
+            "dt",
+            "em",
+            "figcaption",
+            "figure",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "hr",
+            "i",
+            "img",
+            "input",
+            "ins",
+            "kbd",
+            "li",
+            "mark",
+            "ol",
+            "p",
+            "pre",
+            "q",
+            "s",
+            "section",
+            "small",
+            "span",
+            "strong",
+            "sub",
+            "summary",
+            "sup",
+            "table",
+            "tbody",
+            "td",
+            "tfoot",
+            "th",
+            "thead",
+            "tr",
+            "u",
+            "ul",
+        ];
+        let generic_attributes = ["align", "class", "id", "lang", "title"];
+        let tag_attributes = [
+            ("a", vec!["href"]),
+            ("img", vec!["src", "alt", "width", "height"]),
+            ("input", vec!["type", "checked", "disabled"]),
+            ("ol", vec!["start"]),
+            ("li", vec!["value"]),
+            ("td", vec!["colspan", "rowspan"]),
+            ("th", vec!["colspan", "rowspan"]),
+        ];
+
+        SanitizeOptions {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            generic_attributes: generic_attributes.iter().map(|a| a.to_string()).collect(),
+            tag_attributes: tag_attributes
+                .into_iter()
+                .map(|(t, attrs)| (t.to_string(), attrs.iter().map(|a| a.to_string()).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl SanitizeOptions {
+    // Build the sanitizer by the allowlist
+    pub(crate) fn builder(&self) -> ammonia::Builder<'_> {
+        let mut b = ammonia::Builder::default();
+        b.tags(
+            self.tags
+                .iter()
+                .map(|t| t.as_str())
+                .filter(|t| *t != "script" && *t != "style")
+                .collect::<HashSet<&str>>(),
+        )
+        .generic_attributes(self.generic_attributes.iter().map(|a| a.as_str()).collect())
+        .tag_attributes(
+            self.tag_attributes
+                .iter()
+                .map(|(t, attrs)| (t.as_str(), attrs.iter().map(|a| a.as_str()).collect()))
+                .collect(),
+        );
+        b
+    }
+}
 
 #[derive(Debug)]
 pub struct Markdown<'markdown> {
@@ -47,6 +252,16 @@ impl<'markdown> Markdown<'markdown> {
         Ok(s)
     }
 
+    // Get the metadata from the front matter of the document, it's available after parsing
+    pub fn metadata(&self) -> &Metadata {
+        self.ast.metadata()
+    }
+
+    // Determine whether the document contains any math, it's available after parsing
+    pub fn has_math(&self) -> bool {
+        self.ast.has_math()
+    }
+
     fn parse(&mut self) -> Result<&Self, Box<dyn Error>> {
         match self.text {
             Some(s) => self.ast.parse_string(s)?,
@@ -61,27 +276,65 @@ impl<'markdown> Markdown<'markdown> {
 
 // Convert markdown ast into body part of the html and it contains toc
 pub fn to_body_toc(ast: &Ast) -> Result<Vec<String>, Box<dyn Error>> {
-    let body = ast.generate_content(&html::Generator::new(ast.ref_link_tags())?);
-    let toc = ast.generate_toc(&html::Generator::new(ast.ref_link_tags())?);
+    to_body_toc_with(ast, &HtmlOptions::default())
+}
+
+// Convert markdown ast into body part of the html and it contains toc, with the options
+pub fn to_body_toc_with(ast: &Ast, opts: &HtmlOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    let body = ast.generate_content(&html::Generator::new(
+        ast.ref_link_tags(),
+        ast.footnotes(),
+        opts,
+    )?);
+    let toc = ast.generate_toc(
+        &opts.toc,
+        &html::Generator::new(ast.ref_link_tags(), ast.footnotes(), opts)?,
+    );
     let v = vec![toc, body];
     Ok(v)
 }
 
 // Convert markdown ast into body part of the html
 pub fn to_body(ast: &Ast) -> Result<Vec<String>, Box<dyn Error>> {
-    let body = ast.generate_content(&html::Generator::new(ast.ref_link_tags())?);
+    to_body_with(ast, &HtmlOptions::default())
+}
+
+// Convert markdown ast into body part of the html with the options
+pub fn to_body_with(ast: &Ast, opts: &HtmlOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    let body = ast.generate_content(&html::Generator::new(
+        ast.ref_link_tags(),
+        ast.footnotes(),
+        opts,
+    )?);
     let v = vec![body];
     Ok(v)
 }
 
 // Generate the toc part of the html from markdown ast
 pub fn to_toc(ast: &Ast) -> Result<Vec<String>, Box<dyn Error>> {
-    let toc = ast.generate_toc(&html::Generator::new(ast.ref_link_tags())?);
+    to_toc_with(ast, &HtmlOptions::default())
+}
+
+// Generate the toc part of the html from markdown ast with the options
+pub fn to_toc_with(ast: &Ast, opts: &HtmlOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    let toc = ast.generate_toc(
+        &opts.toc,
+        &html::Generator::new(ast.ref_link_tags(), ast.footnotes(), opts)?,
+    );
     let v = vec![toc];
     Ok(v)
 }
 
 // Generate the slice of markdown
 pub fn to_slice(ast: &Ast) -> Result<Vec<String>, Box<dyn Error>> {
-    Ok(ast.generate_slice(&html::Generator::new(ast.ref_link_tags())?))
+    to_slice_with(ast, &HtmlOptions::default())
+}
+
+// Generate the slice of markdown with the options
+pub fn to_slice_with(ast: &Ast, opts: &HtmlOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(ast.generate_slice(&html::Generator::new(
+        ast.ref_link_tags(),
+        ast.footnotes(),
+        opts,
+    )?))
 }
@@ -7,11 +7,83 @@ use std::rc::Rc;
 use std::{fmt, io};
 
 use crate::lexer::{Lexer, Token, TokenKind};
+use crate::markdown::TocOptions;
+use crate::utils;
 use crate::Generate;
+use crate::Metadata;
 use crate::SharedLine;
 
 use itertools::Itertools;
 
+// The tags that begin a html block which ends before a blank line
+const HTML_BLOCK_TAGS: [&str; 62] = [
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+// The kinds of the callout (GitHub alerts), .e.g "> [!NOTE]"
+const CALLOUT_KINDS: [&str; 5] = ["note", "tip", "important", "warning", "caution"];
+
 // Ast represents the abstract syntax tree of the markdown file, it structurally represents the entire file.
 pub struct Ast {
     // Store all parsed line structs in order
@@ -20,8 +92,23 @@ pub struct Ast {
     blocks: Vec<Block>,
     // Store all tags of the ref link, the map is "tag -> (location, title)"
     ref_link_tags: HashMap<String, (String, String)>,
-    // The block of the table of contents, it's a unordered list
-    toc: Vec<Block>,
+    // Store all definitions of the footnote, the map is "label -> text"
+    footnotes: HashMap<String, String>,
+    // The metadata parsed from the front matter at the beginning of the document
+    metadata: Metadata,
+    // The ast of the content of the quote, list item or container, it has no front matter
+    nested: bool,
+}
+
+// The state of the blocks that are not closed when the lines are parsed one by one
+#[derive(Default)]
+struct ParseState {
+    // the fence and indent of the opening code block mark
+    fence: Option<(String, usize)>,
+    // the lines of the math block that is not closed
+    math: Option<Vec<SharedLine>>,
+    // the end condition of the html block that is not closed
+    html: Option<HtmlBlockEnd>,
 }
 
 impl Ast {
@@ -31,7 +118,17 @@ impl Ast {
             document: vec![Rc::new(RefCell::new(Line::meta()))],
             blocks: vec![],
             ref_link_tags: HashMap::new(),
-            toc: vec![],
+            footnotes: HashMap::new(),
+            metadata: HashMap::new(),
+            nested: false,
+        }
+    }
+
+    // Create a Ast instance for the content of the quote, list item or container
+    fn new_nested() -> Self {
+        Ast {
+            nested: true,
+            ..Self::new()
         }
     }
 
@@ -57,8 +154,8 @@ impl Ast {
 
     // Parse markdown document from a reader, the 'reader' may be a file reader, byte buff or network socket etc.
     pub(crate) fn parse_from(&mut self, reader: &mut dyn BufRead) -> Result<(), io::Error> {
-        let mut is_lazy = false;
-        let mut lazy_queue: Vec<SharedLine> = vec![];
+        let mut state = ParseState::default();
+        let mut front_matter: Option<Vec<SharedLine>> = None;
 
         let mut ln: usize = 0;
 
@@ -72,50 +169,153 @@ impl Ast {
             let l = Line::new(ln, buf);
             let lref = Rc::new(RefCell::new(l));
 
-            // add the scope block to reduce the lifecycle of the 'l' (l = lrc.borrow_mut())
-            {
-                let mut l = lref.borrow_mut();
-
-                match l.pre_parse() {
-                    Kind::TocPosition => l.weak_parse(Kind::TocPosition),
-                    Kind::CodeBlockMark => {
-                        l.strong_parse();
-                        if is_lazy {
-                            lazy_queue.clear();
-                        }
-                        is_lazy = !is_lazy;
-                    }
-                    Kind::PlainText => {
-                        if is_lazy {
-                            // lazy parsing
-                            lazy_queue.push(Rc::clone(&lref));
-                            l.weak_parse(Kind::CodeBlock);
-                        } else {
-                            l.strong_parse();
-                        }
+            // the front matter must be at the beginning of the document, its lines are parsed
+            // lazily until the closing mark is found.
+            if ln == 1 && !self.nested && lref.borrow().is_front_matter_mark() {
+                front_matter = Some(vec![]);
+            }
+            if let Some(queue) = front_matter.as_mut() {
+                lref.borrow_mut().weak_parse(Kind::Meta__);
+                queue.push(Rc::clone(&lref));
+
+                let closed = lref.borrow().text().trim_end() == queue[0].borrow().text().trim_end();
+                if queue.len() > 1 && closed {
+                    let queue = front_matter.take().unwrap_or_default();
+                    if !self.parse_front_matter(&queue) {
+                        queue.iter().for_each(|l| self.parse_line(l, &mut state));
                     }
-                    _ => unreachable!(),
                 }
-
-                // postpone
-                l.pick_reflink_tags(&mut self.ref_link_tags);
+                self.document.push(lref);
+                continue;
             }
+
+            self.parse_line(&lref, &mut state);
             self.document.push(lref);
 
             debug_assert_eq!(self.count_lines(), ln);
             debug_assert_eq!(self.document[ln].borrow().num, ln);
         } // end of loop
 
-        lazy_queue
-            .iter()
-            .for_each(|l| l.borrow_mut().strong_parse());
-        lazy_queue.clear();
+        // the front matter is not closed, so it's parsed as normal lines
+        if let Some(queue) = front_matter.take() {
+            queue.iter().for_each(|l| self.parse_line(l, &mut state));
+        }
+        // the math block is not closed, so it's parsed as normal lines
+        if let Some(queue) = state.math.take() {
+            self.reparse_lines(&queue);
+        }
         self.init_content_block();
-        self.init_toc_block();
+        self.assign_title_ids();
 
         Ok(())
     }
 
+    // Parse the line by the state of the blocks that are not closed, the previous lines of the
+    // line must be in the document already.
+    fn parse_line(&mut self, lref: &SharedLine, state: &mut ParseState) {
+        let mut l = lref.borrow_mut();
+
+        if let Some(queue) = state.math.as_mut() {
+            // the lines in the math block are parsed lazily until the closing mark is found
+            l.weak_parse(Kind::MathBlock);
+            queue.push(Rc::clone(lref));
+            if l.is_math_block_end(false) {
+                state.math = None;
+            }
+        } else if let Some(end) = state.html {
+            // the html block of the kind 'BlankLine' doesn't contain the blank line
+            if end == HtmlBlockEnd::BlankLine && l.text().trim().is_empty() {
+                l.strong_parse();
+                state.html = None;
+            } else {
+                l.weak_parse(Kind::HtmlBlock);
+                if l.is_html_block_end(end) {
+                    state.html = None;
+                }
+            }
+        } else if let Some((mark, indent)) = state.fence.as_ref() {
+            // the lines in the code block are not parsed until the closing mark is found,
+            // the code block is closed by the end of document if it's not closed.
+            if l.is_closing_fence(mark) {
+                l.strong_parse();
+                state.fence = None;
+            } else {
+                l.parse_code(*indent);
+            }
+        } else {
+            match l.pre_parse() {
+                Kind::TocPosition => l.weak_parse(Kind::TocPosition),
+                Kind::CodeBlockMark => {
+                    l.strong_parse();
+                    state.fence = Lexer::split_fence(l.text())
+                        .map(|(mark, indent)| (mark.to_string(), indent));
+                }
+                Kind::MathBlock => {
+                    l.weak_parse(Kind::MathBlock);
+                    if !l.is_math_block_end(true) {
+                        state.math = Some(vec![Rc::clone(lref)]);
+                    }
+                }
+                Kind::PlainText => {
+                    let after_text = self
+                        .document
+                        .get(l.num - 1)
+                        .is_some_and(|p| p.borrow().kind == Kind::PlainText);
+                    match l.html_block_begin(after_text) {
+                        Some(end) => {
+                            l.weak_parse(Kind::HtmlBlock);
+                            if !l.is_html_block_end(end) {
+                                state.html = Some(end);
+                            }
+                        }
+                        None => l.strong_parse(),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // Parse the front matter into the metadata, the 'lines' contains the opening and closing marks.
+    // It returns false if the front matter is invalid or empty, then the lines should be parsed as
+    // normal lines, .e.g "---\n# Title\n---" is a title between the dividing lines rather than a
+    // YAML comment.
+    fn parse_front_matter(&mut self, lines: &[SharedLine]) -> bool {
+        debug_assert!(lines.len() >= 2);
+
+        let text: String = lines[1..lines.len() - 1]
+            .iter()
+            .map(|l| l.borrow().text().to_string())
+            .collect();
+
+        let metadata = match lines[0].borrow().text().trim_end() {
+            "---" => serde_yaml::from_str::<Option<Metadata>>(&text)
+                .ok()
+                .flatten(),
+            "+++" => toml::from_str::<toml::Table>(&text).ok().map(|t| {
+                t.into_iter()
+                    .map(|(k, v)| (k, utils::toml_to_json(v)))
+                    .collect()
+            }),
+            _ => None,
+        };
+
+        match metadata.filter(|m| !m.is_empty()) {
+            Some(m) => {
+                self.metadata = m;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Parse the lines that were parsed lazily as normal lines
+    fn reparse_lines(&mut self, lines: &[SharedLine]) {
+        for l in lines {
+            l.borrow_mut().strong_parse();
+        }
+    }
+
     fn read_line(reader: &mut dyn BufRead) -> Result<(usize, String), io::Error> {
         let mut buf = String::new();
         let num_bytes = reader.read_line(&mut buf)?;
@@ -128,14 +328,15 @@ impl Ast {
         Ok((num_bytes, buf))
     }
 
-    // Generate the contents of the document
+    // Generate the contents of the document, the footnotes are placed at the end
     pub(crate) fn generate_content(&self, generator: &impl Generate) -> String {
-        self.generate(self.content_blocks(), generator)
+        let s = generator.render_contents(self.generate(self.content_blocks(), generator));
+        Self::append_footnotes(s, generator)
     }
 
-    // Generate the table of contents based on the title blocks and we skipped the level 1 title
-    pub(crate) fn generate_toc(&self, generator: &impl Generate) -> String {
-        self.generate(&self.toc, generator)
+    // Generate the table of contents based on the title blocks with the options
+    pub(crate) fn generate_toc(&self, opts: &TocOptions, generator: &impl Generate) -> String {
+        self.generate(&self.establish_toc(opts), generator)
     }
 
     // Generate the slice of the contents of the document based on the dividing blocks
@@ -144,14 +345,28 @@ impl Ast {
         let mut start = 0;
         for (i, b) in self.blocks.iter().enumerate() {
             if b.kind() == Kind::Dividing {
-                v.push(self.generate(&self.blocks[start..i], generator));
+                let s = generator.render_contents(self.generate(&self.blocks[start..i], generator));
+                v.push(Self::append_footnotes(s, generator));
                 start = i + 1;
             }
         }
-        v.push(self.generate(&self.blocks[start..], generator));
+        let s = generator.render_contents(self.generate(&self.blocks[start..], generator));
+        v.push(Self::append_footnotes(s, generator));
         v
     }
 
+    // Append the footnotes referenced by the generated contents
+    fn append_footnotes(mut s: String, generator: &impl Generate) -> String {
+        let footnotes = generator.render_footnotes();
+        if !footnotes.is_empty() {
+            if !s.is_empty() {
+                s.push_str("\n\n");
+            }
+            s.push_str(&footnotes);
+        }
+        s
+    }
+
     // Iterate through each block of the Ast and process the block into a 'html' string
     fn generate(&self, blocks: &[Block], generator: &impl Generate) -> String {
         blocks
@@ -164,17 +379,19 @@ impl Ast {
                 Kind::CodeBlock => generator.render_code(b.contains()),
                 Kind::UnorderedList => generator.render_unordered_list(b.contains()),
                 Kind::Blank => generator.render_blank(b.contains()),
-                Kind::Quote => {
-                    let s = b
-                        .quote_ast
-                        .as_ref()
-                        .map(|a| a.generate_content(generator))
-                        .unwrap_or_else(|| "".to_string());
-                    generator.render_quote(&s)
-                }
+                Kind::Quote => b.generate_quote(generator),
+                Kind::Container => b.generate_container(generator),
+                Kind::Columns => b.generate_columns(generator),
                 Kind::OrderedList => generator.render_ordered_list(b.contains()),
-                Kind::CodeBlockMark => generator.render_plain_text(b.contains()), // treat code block mark as plain text
-                Kind::TocPosition => self.generate_toc(generator), // Note: here is a toc position
+                Kind::DefinitionList => generator.render_definition_list(b.contains()),
+                Kind::Table => generator.render_table(b.contains()),
+                Kind::MathBlock => generator.render_math(b.contains()),
+                Kind::HtmlBlock => generator.render_raw_html(b.contains()),
+                // Note: here is a toc position, the marker may specify the options
+                Kind::TocPosition => {
+                    let opts = TocOptions::from_marker(b.first().borrow().text());
+                    self.generate_toc(&opts.unwrap_or_default(), generator)
+                }
                 _ => unreachable!(),
             })
             .filter(|s| !s.is_empty())
@@ -190,69 +407,256 @@ impl Ast {
         &self.ref_link_tags
     }
 
+    pub(crate) fn footnotes(&self) -> &HashMap<String, String> {
+        &self.footnotes
+    }
+
+    // Determine whether the document contains any math, the math in the quotes is included
+    pub(crate) fn has_math(&self) -> bool {
+        let in_lines = self.document.iter().any(|l| {
+            let l = l.borrow();
+            l.kind == Kind::MathBlock || l.all().iter().any(|t| t.kind() == TokenKind::Math)
+        });
+        in_lines
+            || self
+                .blocks
+                .iter()
+                .filter_map(|b| b.sub_ast.as_ref())
+                .any(|a| a.has_math())
+    }
+
+    // Get the metadata of the document, it's empty if there is no front matter
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
     fn content_blocks(&self) -> &Vec<Block> {
         &self.blocks
     }
 
-    fn init_toc_block(&mut self) {
-        const MIN_LEVEL: usize = 1;
-        const MAX_LEVEL: usize = 6;
-
-        let mut lines: Vec<SharedLine> = vec![];
+    // Establish the lists of the toc by the titles in the range of levels, the titles nested in
+    // the list items or the quotes are not included.
+    fn establish_toc(&self, opts: &TocOptions) -> Vec<Block> {
+        let titles: Vec<(usize, &SharedLine)> = self
+            .blocks
+            .iter()
+            .filter(|b| b.kind() == Kind::Title)
+            .map(|b| (b.first().borrow().mark_token().len(), b.first()))
+            .filter(|(level, l)| {
+                *level >= opts.min_level
+                    && *level <= opts.max_level
+                    && !l.borrow().has_class(&opts.exclude)
+            })
+            .collect();
+        Self::establish_toc_list(&titles, opts, "")
+            .into_iter()
+            .collect()
+    }
 
-        for l in self.document.iter().filter(|l| {
-            l.borrow().kind == Kind::Title
-                && l.borrow().mark_token().len() >= MIN_LEVEL
-                && l.borrow().mark_token().len() <= MAX_LEVEL
-        }) {
-            let mut buff: Vec<Token> = vec![];
+    // Build the list of the toc recursively, the titles following a title are nested in its item
+    // until the title of the same or higher level, .e.g "###" following "##". The 'prefix' is the
+    // section number of the parent item, .e.g "1.2."
+    fn establish_toc_list(
+        titles: &[(usize, &SharedLine)],
+        opts: &TocOptions,
+        prefix: &str,
+    ) -> Option<Block> {
+        let kind = if opts.ordered {
+            Kind::OrderedList
+        } else {
+            Kind::UnorderedList
+        };
+        let mut list: Option<Block> = None;
+        let mut i = 0;
+        let mut seq = 1;
+        while i < titles.len() {
+            let (level, l) = titles[i];
+            let end = titles[i + 1..]
+                .iter()
+                .position(|(x, _)| *x <= level)
+                .map_or(titles.len(), |n| i + 1 + n);
 
-            // create a new indent token with white space.
-            let level = l.borrow().mark_token().len();
-            if level > MIN_LEVEL {
-                buff.push(Token::new(
-                    "  ".repeat(level - MIN_LEVEL),
-                    TokenKind::WhiteSpace,
-                ));
+            let (id, mut name) = l.borrow().anchor();
+            let number = format!("{}{}", prefix, seq);
+            if opts.numbering {
+                name = format!("{} {}", number, name);
             }
 
-            // create a new unordered mark token
-            buff.push(Token::new("*".to_string(), TokenKind::UnorderedMark));
-
-            // create a new link token
-            let (id, name) = l.borrow().anchor();
+            // create a new list mark token and link token
+            let mark = match kind {
+                Kind::OrderedList => Token::new(format!("{}.", seq), TokenKind::OrderedMark),
+                _ => Token::new("*".to_string(), TokenKind::UnorderedMark),
+            };
             let location = format!("#{}", id);
-
             let mut t = Token::new(format!("[{}]({})", name, location), TokenKind::Link);
             t.as_generic_link_mut().insert_name(&name);
             t.as_generic_link_mut().insert_location(&location);
-            buff.push(t);
 
-            // create a new line for toc
-            let l2 = Line {
-                kind: Kind::UnorderedList,
-                buff,
+            // create a new line for toc, the deeper titles are the nested list of the item
+            let nested = Self::establish_toc_list(&titles[i + 1..end], opts, &(number + "."));
+            let item = Rc::new(RefCell::new(Line {
+                kind,
+                buff: vec![mark, t],
                 num: l.borrow().num,
                 text: name,
                 nested_lines: vec![],
-                nested_blocks: vec![],
-            };
+                nested_blocks: nested.into_iter().collect(),
+                attrs: vec![],
+                id: String::new(),
+            }));
+            match list.as_mut() {
+                Some(b) => b.push(item),
+                None => list = Some(Block::new(item, kind)),
+            }
 
-            lines.push(Rc::new(RefCell::new(l2)));
+            i = end;
+            seq += 1;
         }
+        list
+    }
+
+    // Assign the unique ids to the titles in the order of the document, the titles nested in the
+    // quotes, containers and list items share the ids with the top level titles. The custom ids
+    // in the attributes are used as they are, so they're reserved before the ids are generated.
+    fn assign_title_ids(&self) {
+        let mut slugger = utils::Slugger::new();
+        Self::for_each_title(&self.blocks, &mut |l| {
+            if let Some(id) = l.borrow().custom_id() {
+                slugger.reserve(id);
+            }
+        });
+        Self::for_each_title(&self.blocks, &mut |l| {
+            let mut l = l.borrow_mut();
+            l.id = match l.custom_id() {
+                Some(id) => id.to_string(),
+                None => slugger.slug(&Line::plain_text(l.all())),
+            };
+        });
+    }
 
-        self.toc = Self::establish_blocks(&lines);
+    // Visit the titles in the order of the document, including the titles nested in the quotes,
+    // containers and list items.
+    fn for_each_title(blocks: &[Block], f: &mut impl FnMut(&SharedLine)) {
+        for b in blocks {
+            if b.kind() == Kind::Title {
+                f(b.first());
+            }
+            if let Some(ast) = b.sub_ast.as_ref() {
+                Self::for_each_title(ast.content_blocks(), f);
+            }
+            for l in b.contains() {
+                Self::for_each_title(&l.borrow().nested_blocks, f);
+            }
+        }
     }
 
     fn init_content_block(&mut self) {
         self.blocks = Self::establish_blocks(&self.document);
+        Self::parse_list_items(&self.blocks);
+        self.ref_link_tags.clear();
+        self.footnotes.clear();
+        Self::pick_definitions(&self.blocks, &mut self.ref_link_tags, &mut self.footnotes);
+    }
+
+    // Pick the definitions of the ref links and footnotes in the order of the document, including
+    // the ones in the sub-documents, .e.g "> [tag]: https://example.com". The first definition
+    // takes precedence if the labels are duplicated, no matter whether it's nested.
+    fn pick_definitions(
+        blocks: &[Block],
+        tags: &mut HashMap<String, (String, String)>,
+        footnotes: &mut HashMap<String, String>,
+    ) {
+        for b in blocks {
+            for l in b.contains() {
+                let l = l.borrow();
+                l.pick_reflink_tags(tags);
+                l.pick_footnotes(footnotes);
+                Self::pick_definitions(&l.nested_blocks, tags, footnotes);
+            }
+            if let Some(ast) = b.sub_ast.as_ref() {
+                for (k, v) in ast.ref_link_tags.iter() {
+                    tags.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+                for (k, v) in ast.footnotes.iter() {
+                    footnotes.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+        }
+    }
+
+    // Parse the content of the list items as the sub-documents, so any kind of blocks can be
+    // nested in the item, .e.g the code block in "1. step\n   ```"
+    fn parse_list_items(blocks: &[Block]) {
+        for b in blocks.iter().filter(|b| {
+            b.kind() == Kind::UnorderedList
+                || b.kind() == Kind::OrderedList
+                || b.kind() == Kind::DefinitionList
+        }) {
+            for l in b.contains() {
+                let mut l = l.borrow_mut();
+                if l.kind == Kind::Description {
+                    // the description is parsed as a whole, .e.g ": text\n\n  more text"
+                    l.nested_blocks = Self::parse_item_content(&l, true);
+                    continue;
+                }
+                if l.kind != Kind::UnorderedList && l.kind != Kind::OrderedList {
+                    // the terms and blank lines of the definition list
+                    continue;
+                }
+                let leads = l.leads_block();
+                if leads || !l.nested_lines.is_empty() {
+                    l.nested_blocks = Self::parse_item_content(&l, leads);
+                }
+                if leads {
+                    // the content of the line is parsed as a block, only the marks are left
+                    l.buff.retain(|t| {
+                        matches!(
+                            t.kind(),
+                            TokenKind::WhiteSpace
+                                | TokenKind::UnorderedMark
+                                | TokenKind::OrderedMark
+                        )
+                    });
+                }
+            }
+        }
+    }
+
+    // Parse the nested lines of the list item into blocks, the indentation of the item content
+    // is removed from the lines, .e.g 2 columns of "- item". The content of the item line is
+    // included if it begins a block, .e.g "- > quote".
+    fn parse_item_content(item: &Line, leads: bool) -> Vec<Block> {
+        let indent = item.content_indent();
+        let mut text = if leads {
+            item.inline_text().to_string()
+        } else {
+            String::new()
+        };
+        item.nested_lines.iter().enumerate().for_each(|(i, l)| {
+            let l = l.borrow();
+            let s = Line::strip_indent(l.text(), indent);
+            // the indented code can't interrupt the text of the item, so the line following
+            // the item is a part of the text, .e.g "- item\n      continued"
+            if i == 0 && !leads && Line::strip_indent(s, 3).starts_with([' ', '\t']) {
+                text.push_str(s.trim_start_matches([' ', '\t']));
+            } else {
+                text.push_str(s);
+            }
+        });
+
+        let mut ast = Ast::new_nested();
+        ast.parse_string(&text).unwrap_or_else(|_e| unreachable!());
+        std::mem::take(&mut ast.blocks)
     }
 
+    // Establish the blocks from the lines
     fn establish_blocks(lines: &[SharedLine]) -> Vec<Block> {
         let mut blocks: Vec<Block> = vec![];
 
         let mut leader: Option<&SharedLine> = None;
         let mut state: Option<Kind> = None;
+        // the fences of the opened containers, the inner containers are closed first
+        let mut fences: Vec<usize> = vec![];
 
         let mut iter = lines
             .iter()
@@ -264,19 +668,54 @@ impl Ast {
             let curr_state = state.unwrap_or(curr_line.kind);
 
             match curr_state {
+                _ if state.is_none() && Self::is_indented_code(&curr_line, &blocks) => {
+                    curr_line.parse_code(4);
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::CodeBlock));
+
+                    // the trailing blank lines are not a part of the indented code block
+                    if iter
+                        .clone()
+                        .find(|l| l.borrow().kind != Kind::Blank)
+                        .is_some_and(|l| l.borrow().indent_width() >= 4)
+                    {
+                        state = Some(Kind::IndentedCode__);
+                    }
+                }
+                Kind::IndentedCode__ => {
+                    curr_line.parse_code(4);
+                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::CodeBlock) {
+                        b.push(Rc::clone(l));
+                    }
+
+                    let next = iter.clone().find(|l| l.borrow().kind != Kind::Blank);
+                    if next.is_none_or(|l| l.borrow().indent_width() < 4) {
+                        state = None;
+                    }
+                }
+                Kind::OrderedList
+                    if curr_line.list_start() != Some(1)
+                        && blocks.last().is_some_and(|b| b.kind() == Kind::PlainText) =>
+                {
+                    // only the ordered list starting with 1 can interrupt a paragraph
+                    curr_line.downgrade_to_text();
+                    if let Some(b) = blocks.last_mut() {
+                        b.push(Rc::clone(l));
+                    }
+                }
                 Kind::UnorderedList | Kind::OrderedList => {
-                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == curr_line.kind) {
+                    if let Some(b) = blocks
+                        .last_mut()
+                        .filter(|b| curr_line.is_same_list(&b.first().borrow()))
+                    {
                         b.push(Rc::clone(l));
                     } else {
                         Self::insert_block(&mut blocks, Block::new(Rc::clone(l), curr_line.kind));
                     }
 
                     // determine whether the next line is a list nesting
-                    if let Some(next) = iter.peek() {
-                        if next.borrow().is_nested(&curr_line) > 0 {
-                            state = Some(Kind::ListNesting__);
-                            leader = Some(l); // save the previous line object as leader
-                        }
+                    if Self::is_item_continued(iter.clone(), &curr_line) {
+                        state = Some(Kind::ListNesting__);
+                        leader = Some(l); // save the previous line object as leader
                     }
                 }
                 Kind::ListNesting__ => {
@@ -287,26 +726,17 @@ impl Ast {
                         let mut ld = ld.borrow_mut();
                         ld.nested_lines.push(Rc::clone(l));
 
-                        if let Some(next) = iter.peek() {
-                            if next.borrow().is_nested(&ld) <= 0 {
-                                (state, leader) = (None, None);
-                            }
+                        if !Self::is_item_continued(iter.clone(), &ld) {
+                            (state, leader) = (None, None);
                         }
                     }
                 }
                 Kind::CodeBlockMark => {
-                    let mut k: Option<Kind> = None;
-                    if let Some(next) = iter.peek() {
-                        let next = next.borrow();
-                        if next.kind == Kind::CodeBlockMark || next.kind == Kind::CodeBlock {
-                            k = Some(Kind::CodeBlock);
-                            state = Some(Kind::CodeBlock);
-                        }
+                    // the opening mark of the code block, the block may be empty and not closed
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::CodeBlock));
+                    if iter.peek().is_some() {
+                        state = Some(Kind::CodeBlock);
                     }
-                    Self::insert_block(
-                        &mut blocks,
-                        Block::new(Rc::clone(l), k.unwrap_or(Kind::CodeBlockMark)),
-                    );
                 }
                 Kind::CodeBlock => {
                     let b = blocks.last_mut().filter(|b| b.kind() == Kind::CodeBlock);
@@ -321,94 +751,408 @@ impl Ast {
                         state = None;
                     }
                 }
-                Kind::Blank | Kind::Quote | Kind::PlainText => {
-                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == curr_line.kind) {
+                Kind::HtmlBlock => {
+                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::HtmlBlock) {
                         b.push(Rc::clone(l));
                     } else {
-                        Self::insert_block(&mut blocks, Block::new(Rc::clone(l), curr_line.kind));
+                        Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::HtmlBlock));
                     }
                 }
-                Kind::Dividing => {
-                    // get kind of the previous line
-                    let prev = lines.get(curr_line.num - 1).map(|v| v.borrow().kind);
-                    // get kind of the next line
-                    let next = iter.peek().map(|v| v.borrow().kind);
+                Kind::MathBlock if state.is_some() => {
+                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::MathBlock) {
+                        b.push(Rc::clone(l));
+                    }
+                    if curr_line.is_math_block_end(false) {
+                        state = None;
+                    }
+                }
+                Kind::MathBlock => {
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::MathBlock));
+                    if !curr_line.is_math_block_end(true) && iter.peek().is_some() {
+                        state = Some(Kind::MathBlock);
+                    }
+                }
+                Kind::PlainText if curr_line.container_begin().is_some() => {
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::Container));
+                    fences = curr_line.container_begin().into_iter().collect();
+                    if iter.peek().is_some() {
+                        state = Some(Kind::Container);
+                    }
+                }
+                Kind::Container => {
+                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::Container) {
+                        b.push(Rc::clone(l));
+                    }
 
-                    if prev.unwrap_or(Kind::Blank) == Kind::Blank
-                        && next.unwrap_or(Kind::Blank) == Kind::Blank
+                    // the fences in the code block or math block are a part of the content
+                    if let Some(n) = curr_line.container_begin() {
+                        fences.push(n);
+                    } else if fences
+                        .last()
+                        .is_some_and(|n| curr_line.is_container_end(*n))
                     {
-                        Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::Dividing))
-                    } else {
-                        // convert dividing to plain text
-                        curr_line.kind = Kind::PlainText;
-                        curr_line
-                            .buff
-                            .iter_mut()
-                            .for_each(|t| t.downgrade_to_text());
-
-                        if let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::PlainText) {
-                            b.push(Rc::clone(l));
+                        fences.pop();
+                        if fences.is_empty() {
+                            state = None;
+                        }
+                    }
+                }
+                Kind::PlainText
+                    if curr_line.begins_description() && Self::ends_with_terms(&blocks) =>
+                {
+                    curr_line.kind = Kind::Description;
+                    Self::insert_description(&mut blocks, Rc::clone(l));
+
+                    // the description may be continued by the indented lines
+                    if Self::is_item_continued(iter.clone(), &curr_line) {
+                        state = Some(Kind::ListNesting__);
+                        leader = Some(l);
+                    }
+                }
+                Kind::PlainText if Self::is_lazy_continuation(&blocks) => {
+                    // the paragraph in the quote continues without the mark, .e.g "> a\nb"
+                    if let Some(b) = blocks.last_mut() {
+                        b.push(Rc::clone(l));
+                    }
+                }
+                Kind::PlainText if Self::is_setext_title(&curr_line, iter.peek()) => {
+                    // the next line is the underline of the title, .e.g "===" or "---"
+                    if let Some(underline) = iter.next() {
+                        let level = if underline.borrow().text().trim().starts_with('=') {
+                            1
                         } else {
-                            Self::insert_block(
-                                &mut blocks,
-                                Block::new(Rc::clone(l), Kind::PlainText),
-                            );
+                            2
+                        };
+
+                        // the whole paragraph above the underline is the title, .e.g "a\nb\n==="
+                        let mut lines = Self::take_paragraph(&mut blocks);
+                        let mut text: String = lines
+                            .iter()
+                            .map(|p| p.borrow().inline_text().to_string())
+                            .collect();
+                        text.push_str(curr_line.inline_text());
+                        match lines.first() {
+                            Some(head) => head.borrow_mut().upgrade_to_title(level, &text),
+                            None => curr_line.upgrade_to_title(level, &text),
                         }
+                        lines.push(Rc::clone(l));
+                        lines.push(Rc::clone(underline));
+
+                        let mut b = Block::new(Rc::clone(&lines[0]), Kind::Title);
+                        lines[1..].iter().for_each(|x| b.push(Rc::clone(x)));
+                        Self::insert_block(&mut blocks, b);
+                    }
+                }
+                Kind::PlainText if Self::is_table_head(&curr_line, iter.peek()) => {
+                    curr_line.parse_table_row();
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::Table));
+                    state = Some(Kind::Table);
+                }
+                Kind::Table => {
+                    let b = blocks.last_mut().filter(|b| b.kind() == Kind::Table);
+                    debug_assert!(b.is_some());
+
+                    if let Some(b) = b {
+                        curr_line.parse_table_row();
+                        b.push(Rc::clone(l));
+                    }
+
+                    // the table is broken at the first blank line or beginning of another block
+                    if iter
+                        .peek()
+                        .filter(|n| n.borrow().kind == Kind::PlainText)
+                        .is_none()
+                    {
+                        state = None;
+                    }
+                }
+                Kind::Blank | Kind::Quote | Kind::PlainText => {
+                    if let Some(b) = blocks.last_mut().filter(|b| b.kind() == curr_line.kind) {
+                        b.push(Rc::clone(l));
+                    } else {
+                        Self::insert_block(&mut blocks, Block::new(Rc::clone(l), curr_line.kind));
                     }
                 }
+                Kind::Dividing => {
+                    // Note: the dividing line under a plain text is a setext title, so we don't
+                    // need to check the previous line here.
+                    Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::Dividing))
+                }
                 Kind::Title => {
                     Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::Title));
                 }
                 Kind::TocPosition => {
                     Self::insert_block(&mut blocks, Block::new(Rc::clone(l), Kind::TocPosition));
                 }
-                Kind::Meta__ => unreachable!(),
+                // the kinds of block or the kinds set when the blocks are established
+                Kind::Meta__ | Kind::Columns | Kind::DefinitionList | Kind::Description => {
+                    unreachable!()
+                }
             } // end of match
         } // end of while
 
-        // build nested lists recursively
-        for b in blocks
-            .iter()
-            .filter(|b| b.kind() == Kind::UnorderedList || b.kind() == Kind::OrderedList)
+        Self::parse_quote_block(&mut blocks);
+        Self::parse_container_block(&mut blocks);
+        blocks
+    }
+
+    // Determine whether the blocks end with the terms of the definition list, the terms are the
+    // lines of a paragraph, and the description may follow the previous description, .e.g
+    // "term\n: description". The terms and description may be separated by blank lines.
+    fn ends_with_terms(blocks: &[Block]) -> bool {
+        match blocks.iter().rev().find(|b| b.kind() != Kind::Blank) {
+            Some(b) if b.kind() == Kind::DefinitionList => true,
+            // the definitions of ref link or footnote are not the terms
+            Some(b) if b.kind() == Kind::PlainText => {
+                b.contains().iter().all(|l| !l.borrow().is_definition())
+            }
+            _ => false,
+        }
+    }
+
+    // Insert the description into the definition list, the terms and blank lines before it are
+    // moved into the list. A new list is created if the terms don't follow another list.
+    fn insert_description(blocks: &mut Vec<Block>, l: SharedLine) {
+        let mut moved: Vec<SharedLine> = vec![];
+        let mut take_last = |blocks: &mut Vec<Block>, kind: Kind| {
+            if let Some(b) = blocks.pop_if(|b| b.kind() == kind) {
+                moved.splice(0..0, b.contains);
+            }
+        };
+        take_last(blocks, Kind::Blank);
+        if blocks.last().is_some_and(|b| b.kind() == Kind::PlainText) {
+            take_last(blocks, Kind::PlainText);
+            // the blank lines between two groups of the terms and descriptions
+            let n = blocks.len();
+            if n >= 2 && blocks[n - 2].kind() == Kind::DefinitionList {
+                take_last(blocks, Kind::Blank);
+            }
+        }
+        moved.push(l);
+
+        match blocks
+            .last_mut()
+            .filter(|b| b.kind() == Kind::DefinitionList)
         {
-            b.contains()
-                .iter()
-                .filter(|l| !l.borrow().nested_lines.is_empty())
-                .for_each(|l| {
-                    let mut l = l.borrow_mut();
-                    let mut bs = Self::establish_blocks(&l.nested_lines);
-                    l.nested_blocks.append(&mut bs);
-                });
+            Some(b) => moved.into_iter().for_each(|l| b.push(l)),
+            None => {
+                let mut iter = moved.into_iter();
+                if let Some(first) = iter.next() {
+                    let mut b = Block::new(first, Kind::DefinitionList);
+                    iter.for_each(|l| b.push(l));
+                    Self::insert_block(blocks, b);
+                }
+            }
         }
+    }
 
-        Self::parse_quote_block(&mut blocks);
-        blocks
+    // Determine whether the following lines continue the list item, the blank lines are a part of
+    // the item if they are followed by a nested line or the next item of the same list.
+    fn is_item_continued<'a>(rest: impl Iterator<Item = &'a SharedLine>, item: &Line) -> bool {
+        let mut blank = false;
+        for l in rest {
+            let l = l.borrow();
+            if l.kind == Kind::Blank {
+                blank = true;
+                continue;
+            }
+            return l.is_nested(item) > 0
+                || (blank && l.is_same_list(item) && l.indents() == item.indents());
+        }
+        false
     }
 
     // Parse quote block into a new ast
     fn parse_quote_block(blocks: &mut [Block]) {
         for b in blocks.iter_mut().filter(|b| b.kind() == Kind::Quote) {
-            let mut ast = Ast::new();
+            let mut ast = Ast::new_nested();
 
-            // Since there is a newline(\n) character at the end of each line, so we use empty string ("") to join them
-            let text = b
+            let lines = b
                 .contains()
                 .iter()
                 .map(|e| {
                     let e = e.borrow();
                     let last = e.last_token();
-                    if last.kind() == TokenKind::Text {
+                    if e.kind != Kind::Quote {
+                        // the lazy continuation line has no quote mark
+                        e.text().to_string()
+                    } else if last.kind() == TokenKind::Text {
                         last.value().to_string()
                     } else {
                         "\n".to_string()
                     }
                 })
-                .collect::<Vec<String>>()
-                .join("");
+                .collect::<Vec<String>>();
+
+            // the first line of the callout is not a part of the content
+            b.callout = Self::split_callout(&lines[0]);
+            let skip = usize::from(b.callout.is_some());
+
+            // Since there is a newline(\n) character at the end of each line, so we use empty string ("") to join them
+            let text = lines[skip..].join("");
+
+            ast.parse_string(&text).unwrap_or_else(|_e| unreachable!());
+            b.sub_ast = Some(ast);
+        }
+    }
 
+    // Parse the content of container block into a new ast, the opening and closing fences are
+    // excluded. The container is closed by the end of document if it's not closed.
+    fn parse_container_block(blocks: &mut [Block]) {
+        for b in blocks.iter_mut().filter(|b| b.kind() == Kind::Container) {
+            let ls = b.contains();
+            let info = ls[0].borrow().container_info().to_string();
+            let n = ls[0].borrow().container_fence().unwrap_or(3);
+            let closed = ls.len() > 1 && ls[ls.len() - 1].borrow().is_container_end(n);
+            let end = if closed { ls.len() - 1 } else { ls.len() };
+            let text: String = ls[1..end]
+                .iter()
+                .map(|l| l.borrow().text().to_string())
+                .collect();
+
+            let mut ast = Ast::new_nested();
             ast.parse_string(&text).unwrap_or_else(|_e| unreachable!());
-            b.quote_ast = Some(ast);
+            let c = Container::parse(&info);
+            // the columns are laid out horizontally, .e.g "::: columns 1:2"
+            if c.name == "columns" {
+                b.kind = Kind::Columns;
+            }
+            b.container = Some(c);
+            b.sub_ast = Some(ast);
+        }
+    }
+
+    // Split the kind and title of the callout from the first line of the quote, the title is
+    // optional, .e.g "[!NOTE]" or "[!WARNING] Be careful". The kind is case-insensitive.
+    fn split_callout(s: &str) -> Option<(String, String)> {
+        let (kind, title) = s.trim().strip_prefix("[!")?.split_once(']')?;
+        let kind = kind.to_lowercase();
+        if !CALLOUT_KINDS.contains(&kind.as_str()) {
+            return None;
+        }
+
+        let title = match title.trim() {
+            "" => kind[..1].to_uppercase() + &kind[1..],
+            t => t.to_string(),
+        };
+        Some((kind, title))
+    }
+
+    // Determine whether the last block is a quote that ends with a paragraph, so the following
+    // text is a lazy continuation line of the quote rather than a new paragraph.
+    fn is_lazy_continuation(blocks: &[Block]) -> bool {
+        let last = match blocks
+            .last()
+            .filter(|b| b.kind() == Kind::Quote)
+            .and_then(|b| b.contains().last())
+        {
+            Some(l) => l.borrow(),
+            None => return false,
+        };
+        if last.kind != Kind::Quote {
+            // it's a lazy continuation line already
+            return true;
+        }
+
+        // the innermost content of the nested quotes, .e.g "text" of "> > text"
+        let content = last.text().trim_start_matches(['>', ' ', '\t']);
+        if content.trim().is_empty() {
+            return false;
         }
+        let mut l = Line::new(0, content.to_string());
+        if l.pre_parse() != Kind::PlainText {
+            return false;
+        }
+        l.strong_parse();
+        matches!(
+            l.kind,
+            Kind::PlainText | Kind::UnorderedList | Kind::OrderedList
+        )
+    }
+
+    // Determine whether the line is the text of a setext title, it must be followed by an
+    // underline that only contains '=' (level 1) or '-' (level 2). A single '-' is lexed as an
+    // empty list item, but it can't interrupt a paragraph, so it's an underline, .e.g "Foo\n-".
+    fn is_setext_title(l: &Line, next: Option<&&SharedLine>) -> bool {
+        if l.kind != Kind::PlainText || l.is_definition() {
+            return false;
+        }
+        let next = match next {
+            Some(n)
+                if matches!(
+                    n.borrow().kind,
+                    Kind::PlainText | Kind::Dividing | Kind::UnorderedList
+                ) =>
+            {
+                n.borrow()
+            }
+            _ => return false,
+        };
+
+        let s = next.text().trim();
+        (!s.is_empty() && s.chars().all(|c| c == '='))
+            || (!s.is_empty() && s.chars().all(|c| c == '-'))
+    }
+
+    // Determine whether the line is the header row of a table, it must be followed by a
+    // delimiter row with the same number of cells, .e.g "| --- | :---: |"
+    fn is_table_head(l: &Line, next: Option<&&SharedLine>) -> bool {
+        let next = match next {
+            Some(n) if n.borrow().kind == Kind::PlainText => n.borrow(),
+            _ => return false,
+        };
+
+        match (
+            Line::split_table_row(l.text()),
+            Line::split_table_row(next.text()),
+        ) {
+            (Some(head), Some(delimiter)) => {
+                head.len() == delimiter.len()
+                    && delimiter.iter().all(|c| {
+                        let c = c.strip_prefix(':').unwrap_or(c);
+                        let c = c.strip_suffix(':').unwrap_or(c);
+                        !c.is_empty() && c.chars().all(|ch| ch == '-')
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    // Determine whether the line begins an indented code block, it's indented with at least
+    // four spaces and can't interrupt a paragraph or a list.
+    fn is_indented_code(l: &Line, blocks: &[Block]) -> bool {
+        if l.kind == Kind::Blank || l.kind == Kind::HtmlBlock || l.indent_width() < 4 {
+            return false;
+        }
+        if let Some(Kind::PlainText | Kind::Quote | Kind::Table) = blocks.last().map(|b| b.kind()) {
+            return false;
+        }
+        !matches!(
+            blocks
+                .iter()
+                .rev()
+                .find(|b| b.kind() != Kind::Blank)
+                .map(|b| b.kind()),
+            Some(Kind::UnorderedList | Kind::OrderedList)
+        )
+    }
+
+    // Take the lines of the paragraph at the end of the blocks, the definitions of the ref links
+    // before the text are not a part of the paragraph, .e.g "[a]: /url" of "[a]: /url\ntext"
+    fn take_paragraph(blocks: &mut Vec<Block>) -> Vec<SharedLine> {
+        let Some(b) = blocks.last_mut().filter(|b| b.kind() == Kind::PlainText) else {
+            return vec![];
+        };
+        let n = b
+            .contains()
+            .iter()
+            .rposition(|l| l.borrow().is_definition())
+            .map_or(0, |ix| ix + 1);
+        let lines = b.contains.split_off(n);
+        if b.contains().is_empty() {
+            blocks.pop();
+        }
+        lines
     }
 
     fn insert_block(blocks: &mut Vec<Block>, mut b: Block) {
@@ -449,9 +1193,26 @@ enum Kind {
     Quote,
     CodeBlockMark,
     CodeBlock,
+    Table,
+    MathBlock,
+    HtmlBlock,
+    Container,
+    Columns,
+    DefinitionList,
+    Description,
     TocPosition,
     Meta__,
     ListNesting__,
+    IndentedCode__,
+}
+
+// The condition to end the html block
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum HtmlBlockEnd {
+    // the block ends at the line that contains the string, .e.g "-->"
+    Contains(&'static str),
+    // the block ends before a blank line
+    BlankLine,
 }
 
 // Block is a combination of associated lines.
@@ -460,7 +1221,12 @@ struct Block {
     seq: usize,
     kind: Kind,
     contains: Vec<SharedLine>,
-    quote_ast: Option<Ast>,
+    // The content of the quote or container is parsed into a new ast
+    sub_ast: Option<Ast>,
+    // The kind and title of the callout if the quote begins with "[!KIND]", .e.g ("note", "Note")
+    callout: Option<(String, String)>,
+    // The directive of the container block, .e.g "::: details Click me"
+    container: Option<Container>,
 }
 
 impl Block {
@@ -469,7 +1235,9 @@ impl Block {
             contains: vec![l],
             kind,
             seq: 0,
-            quote_ast: None,
+            sub_ast: None,
+            callout: None,
+            container: None,
         }
     }
 
@@ -488,10 +1256,108 @@ impl Block {
     fn push(&mut self, l: SharedLine) {
         self.contains.push(l)
     }
-}
 
-// Line is a line of the markdown file, it be parsed into some tokens.
-#[derive(Debug)]
+    // Generate the quote or the callout by the parsed content of the quote block
+    fn generate_quote(&self, generator: &impl Generate) -> String {
+        let s = self
+            .sub_ast
+            .as_ref()
+            .map(|a| a.generate(a.content_blocks(), generator))
+            .unwrap_or_default();
+        match &self.callout {
+            Some((kind, title)) => generator.render_callout(kind, title, &s),
+            None => generator.render_quote(&s),
+        }
+    }
+
+    // Generate the container by the parsed content of the container block
+    fn generate_container(&self, generator: &impl Generate) -> String {
+        let s = self.generate_container_content(generator);
+        match &self.container {
+            Some(c) => generator.render_container(&c.name, &c.title, &c.attrs, &s),
+            None => s,
+        }
+    }
+
+    fn generate_container_content(&self, generator: &impl Generate) -> String {
+        self.sub_ast
+            .as_ref()
+            .map(|a| a.generate(a.content_blocks(), generator))
+            .unwrap_or_default()
+    }
+
+    // Generate the columns laid out horizontally, every "column" container in the content is a
+    // column, and the other blocks between them are a column as well. The ratio of the width is
+    // the title of the column, .e.g "::: column 2", or the title of the columns, .e.g
+    // "::: columns 1:2". The default ratio is 1.
+    fn generate_columns(&self, generator: &impl Generate) -> String {
+        let (Some(ast), Some(c)) = (self.sub_ast.as_ref(), self.container.as_ref()) else {
+            return String::new();
+        };
+        let ratios: Vec<u32> = Container::parse_ratios(&c.title);
+        let blocks = ast.content_blocks();
+
+        let mut columns: Vec<(Option<u32>, String)> = vec![];
+        let mut begin = 0;
+        for (i, b) in blocks.iter().enumerate() {
+            let Some(column) = b.container.as_ref().filter(|c| c.name == "column") else {
+                continue;
+            };
+            if blocks[begin..i].iter().any(|b| b.kind() != Kind::Blank) {
+                columns.push((None, ast.generate(&blocks[begin..i], generator)));
+            }
+            columns.push((
+                Container::parse_ratios(&column.title).first().copied(),
+                b.generate_container_content(generator),
+            ));
+            begin = i + 1;
+        }
+        if blocks[begin..].iter().any(|b| b.kind() != Kind::Blank) {
+            columns.push((None, ast.generate(&blocks[begin..], generator)));
+        }
+
+        let columns: Vec<(u32, String)> = columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, (r, s))| (r.or(ratios.get(i).copied()).unwrap_or(1), s))
+            .collect();
+        generator.render_columns(&c.attrs, &columns)
+    }
+}
+
+// The directive of the container block, it's parsed from the info string of the opening fence,
+// .e.g "details Click me {#id .class}". The name is lowercase, the title is the text after the
+// name and the attributes are in the braces at the end.
+#[derive(Debug, PartialEq)]
+struct Container {
+    name: String,
+    title: String,
+    attrs: Vec<(String, String)>,
+}
+
+impl Container {
+    fn parse(info: &str) -> Self {
+        let (rest, attrs) = utils::split_attributes(info);
+        let (name, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        Container {
+            name: name.to_lowercase(),
+            title: title.trim().to_string(),
+            attrs,
+        }
+    }
+
+    // Parse the ratios of the column widths, they're separated by ':' or white space, .e.g
+    // "1:2" or "1 2". The invalid ratio is treated as 1.
+    fn parse_ratios(s: &str) -> Vec<u32> {
+        s.split(|c: char| c == ':' || c.is_whitespace())
+            .filter(|r| !r.is_empty())
+            .map(|r| r.parse::<u32>().ok().filter(|n| *n > 0).unwrap_or(1))
+            .collect()
+    }
+}
+
+// Line is a line of the markdown file, it be parsed into some tokens.
+#[derive(Debug)]
 pub struct Line {
     buff: Vec<Token>,
     kind: Kind,
@@ -505,6 +1371,10 @@ pub struct Line {
     //   Kind::Normal
     nested_lines: Vec<SharedLine>,
     nested_blocks: Vec<Block>,
+    // The attributes at the end of the title, .e.g "## Title {#id .class}"
+    attrs: Vec<(String, String)>,
+    // The unique id of the title in the document, it's assigned after the document is parsed
+    id: String,
 }
 
 impl Line {
@@ -516,6 +1386,8 @@ impl Line {
             buff: vec![],
             nested_lines: vec![], // Note: fill the nested lines when establish blocks
             nested_blocks: vec![], // Note: fill the nested blocks when establish blocks
+            attrs: vec![],
+            id: String::new(),
         }
     }
 
@@ -539,56 +1411,330 @@ impl Line {
             TokenKind::CodeBlockMark => Kind::CodeBlockMark,
             _ => Kind::PlainText,
         };
+        if self.kind == Kind::Title {
+            self.split_title_attributes();
+        }
 
         debug_assert!(!self.all().is_empty());
     }
 
+    // Split the attributes at the end of the title, .e.g "## Title {#id .class}", the title is
+    // split into tokens again without the attributes. The attributes may be followed by the
+    // closing sequence, .e.g "## Title {#id} ##".
+    fn split_title_attributes(&mut self) {
+        let text = self.text.trim_end();
+        let text = match text.trim_end_matches('#') {
+            t if t.len() < text.len() && t.ends_with([' ', '\t']) => t,
+            _ => text,
+        };
+        let (rest, attrs) = utils::split_attributes(text);
+        if attrs.is_empty() {
+            return;
+        }
+        let buff = Lexer::new(&format!("{}\n", rest)).split();
+        if buff.iter().any(|t| t.kind() == TokenKind::TitleMark) {
+            self.buff = buff;
+            self.attrs = attrs;
+        }
+    }
+
     // Try to parse the line text simply
     fn pre_parse(&self) -> Kind {
         let l = self.text().trim();
-        if l.starts_with("```") {
+        if Lexer::split_fence(self.text()).is_some() {
             // To parse the line of code block mark
             Kind::CodeBlockMark
-        } else if l.starts_with("<!--") && l.ends_with("-->") {
-            // To parse the line of toc position (<!-- -->)
-            let x: &[_] = &['<', '>', '!', '-', ' '];
-            if l.trim_matches(x).eq_ignore_ascii_case("toc") {
-                Kind::TocPosition
-            } else {
-                Kind::PlainText
-            }
+        } else if l.starts_with("$$") && self.indent_width() < 4 {
+            // To parse the line of math block mark
+            Kind::MathBlock
+        } else if TocOptions::from_marker(l).is_some() {
+            // To parse the line of toc position (<!-- toc -->)
+            Kind::TocPosition
         } else {
             Kind::PlainText
         }
     }
 
-    // Get number of the indent, two white space(' ') or one '\t' is a indent
-    fn indents(&self) -> isize {
-        let first = self.first_token();
-        if first.kind() != TokenKind::WhiteSpace {
-            return 0;
+    // Parse the line as a content line of the code block, up to 'indent' columns of the
+    // leading white space are removed.
+    fn parse_code(&mut self, indent: usize) {
+        let content = Self::strip_indent(&self.text, indent);
+        self.buff = vec![Token::new(content.to_string(), TokenKind::Text)];
+        self.kind = Kind::CodeBlock;
+    }
+
+    // Remove up to 'indent' columns of the leading white space from the text
+    fn strip_indent(text: &str, indent: usize) -> &str {
+        let mut width = 0;
+        text.trim_start_matches(|c| {
+            if width >= indent {
+                return false;
+            }
+            match c {
+                ' ' => width += 1,
+                '\t' => width += 4 - width % 4,
+                _ => return false,
+            }
+            true
+        })
+    }
+
+    // Determine whether the line closes the code block opened by the 'mark', the closing mark
+    // must be the same character and at least as long as the opening one.
+    fn is_closing_fence(&self, mark: &str) -> bool {
+        Lexer::split_fence(self.text()).is_some_and(|(fence, indent)| {
+            fence.starts_with(&mark[..1])
+                && fence.len() >= mark.len()
+                && self.text()[indent + fence.len()..].trim().is_empty()
+        })
+    }
+
+    // Get the width of leading white space, a '\t' advances to the next multiple of 4
+    fn indent_width(&self) -> usize {
+        let mut width = 0;
+        for c in self.text().chars() {
+            match c {
+                ' ' => width += 1,
+                '\t' => width += 4 - width % 4,
+                _ => break,
+            }
+        }
+        width
+    }
+
+    // Determine whether the line begins a html block, and return the end condition of the block.
+    // The 'after_text' is true if the line follows a paragraph, a single tag of the unknown name
+    // can't interrupt the paragraph.
+    fn html_block_begin(&self, after_text: bool) -> Option<HtmlBlockEnd> {
+        let s = self.text().trim();
+        if self.indent_width() >= 4 || !s.starts_with('<') {
+            return None;
+        }
+        let lower = s.to_ascii_lowercase();
+
+        // the tag name is followed by white space, '>', "/>" or the end of line
+        let tag_begins = |tag: &str, s: &str| {
+            s.strip_prefix(tag).is_some_and(|rest| {
+                rest.is_empty()
+                    || rest.starts_with(|c: char| c.is_whitespace() || c == '>')
+                    || rest.starts_with("/>")
+            })
+        };
+
+        for (tag, end) in [
+            ("<script", "</script>"),
+            ("<pre", "</pre>"),
+            ("<style", "</style>"),
+            ("<textarea", "</textarea>"),
+        ] {
+            if tag_begins(tag, &lower) {
+                return Some(HtmlBlockEnd::Contains(end));
+            }
+        }
+        if s.starts_with("<!--") {
+            return Some(HtmlBlockEnd::Contains("-->"));
+        }
+        if s.starts_with("<?") {
+            return Some(HtmlBlockEnd::Contains("?>"));
+        }
+        if s.starts_with("<![CDATA[") {
+            return Some(HtmlBlockEnd::Contains("]]>"));
+        }
+        if s.starts_with("<!") && s[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Some(HtmlBlockEnd::Contains(">"));
+        }
+
+        let name = lower.trim_start_matches('<').trim_start_matches('/');
+        if HTML_BLOCK_TAGS.iter().any(|tag| tag_begins(tag, name)) {
+            return Some(HtmlBlockEnd::BlankLine);
         }
-        let sum: isize = first
-            .value()
+
+        // a complete open or closing tag is the whole line
+        let single_tag = utils::match_html_tag(s)
+            .is_some_and(|t| t.len() == s.len() && !t.starts_with("<!") && !t.starts_with("<?"));
+        if single_tag && !after_text {
+            return Some(HtmlBlockEnd::BlankLine);
+        }
+        None
+    }
+
+    // Determine whether the line ends the html block
+    fn is_html_block_end(&self, end: HtmlBlockEnd) -> bool {
+        match end {
+            HtmlBlockEnd::Contains(s) => self.text().to_ascii_lowercase().contains(s),
+            HtmlBlockEnd::BlankLine => false,
+        }
+    }
+
+    // Determine whether the line closes the math block, the 'opening' is true if the line is
+    // the opening line, .e.g "$$ x^2 $$" is closed by itself.
+    fn is_math_block_end(&self, opening: bool) -> bool {
+        let s = self.text().trim();
+        s.ends_with("$$") && (!opening || s.len() >= 4)
+    }
+
+    // Get the fence of the container, it's a sequence of at least three colons(:) and indented
+    // with up to three spaces, .e.g ":::" of "::: warning". Return the length of the fence.
+    fn container_fence(&self) -> Option<usize> {
+        if self.indent_width() > 3 {
+            return None;
+        }
+        let n = self
+            .text()
+            .trim_start()
             .chars()
-            .map(|c| if c == '\t' { 2 } else { 1 })
-            .sum();
+            .take_while(|c| *c == ':')
+            .count();
+        (n >= 3).then_some(n)
+    }
+
+    // Get the info string of the container, .e.g "warning Be careful" of "::: warning Be careful"
+    fn container_info(&self) -> &str {
+        self.text().trim_start().trim_start_matches(':').trim()
+    }
+
+    // Determine whether the line opens a container, the fence is followed by the name of the
+    // container. The lines in the code block and math block are not checked.
+    // Return the length of the fence.
+    fn container_begin(&self) -> Option<usize> {
+        if !matches!(self.kind, Kind::PlainText | Kind::HtmlBlock) {
+            return None;
+        }
+        self.container_fence()
+            .filter(|_| self.container_info().starts_with(char::is_alphanumeric))
+    }
+
+    // Determine whether the line closes the container opened by the fence of 'n' colons, the
+    // closing fence must be at least as long as the opening one.
+    fn is_container_end(&self, n: usize) -> bool {
+        matches!(self.kind, Kind::PlainText | Kind::HtmlBlock)
+            && self.container_info().is_empty()
+            && self.container_fence().is_some_and(|m| m >= n)
+    }
+
+    // Determine whether the line is the opening or closing mark of the fenced code block
+    pub(crate) fn is_fence(&self) -> bool {
+        self.kind == Kind::CodeBlockMark
+    }
+
+    // Get the info string of the fenced code block, .e.g "rust" of "```rust"
+    pub(crate) fn code_info(&self) -> &str {
+        Lexer::split_fence(self.text())
+            .map(|(fence, indent)| self.text()[indent + fence.len()..].trim())
+            .unwrap_or("")
+    }
+
+    // Get the content of the line in the code block
+    pub(crate) fn code_text(&self) -> &str {
+        self.first_token().value()
+    }
+
+    // Convert the line of list item into plain text, it's used by the ordered list that can't
+    // interrupt a paragraph, .e.g "2020. is a year" in the middle of text
+    fn downgrade_to_text(&mut self) {
+        self.buff.retain(|t| t.kind() == TokenKind::WhiteSpace);
+        self.buff
+            .append(&mut Lexer::new(self.text.trim_start()).split_inline_only());
+        self.kind = Kind::PlainText;
+    }
+
+    // Convert the line of plain text into a title, it's used by the setext title
+    fn upgrade_to_title(&mut self, level: usize, text: &str) {
+        let (rest, attrs) = utils::split_attributes(text);
+        let rest = rest.trim_end();
+        // the title of multiple lines is parsed again, the lines are joined by the soft breaks
+        if !attrs.is_empty() || rest.contains('\n') {
+            self.buff = Lexer::new(rest).split_inline_only();
+            self.attrs = attrs;
+        }
+        self.buff
+            .retain(|t| t.kind() != TokenKind::WhiteSpace && t.kind() != TokenKind::LineBreak);
+        self.buff
+            .insert(0, Token::new("#".repeat(level), TokenKind::TitleMark));
+        self.kind = Kind::Title;
+    }
+
+    // Determine whether the line is the opening or closing mark of the front matter,
+    // '---' is for yaml and '+++' is for toml
+    fn is_front_matter_mark(&self) -> bool {
+        let l = self.text().trim_end();
+        l == "---" || l == "+++"
+    }
+
+    // Get number of the indent, two white space(' ') or one '\t' is a indent
+    fn indents(&self) -> isize {
+        let ws = match self.buff.first() {
+            Some(t) if t.kind() == TokenKind::WhiteSpace => t.value(),
+            Some(_) => return 0,
+            // the line parsed weakly has no token, .e.g the line of math block
+            None => &self.text[..self.text.len() - self.text.trim_start().len()],
+        };
+        let sum: isize = ws.chars().map(|c| if c == '\t' { 2 } else { 1 }).sum();
         sum / 2
     }
 
     // Determine whether the current line is a nested line of 'parent'
     // The return value is the number of nested indents, it's not a nested if less than or equal to 0.
     fn is_nested(&self, parent: &Line) -> isize {
-        if self.kind == Kind::Blank
-            || self.kind == Kind::Title
-            || self.kind == Kind::Dividing
-            || self.kind == Kind::CodeBlockMark
-            || self.kind == Kind::CodeBlock
-        {
-            0
-        } else {
-            self.indents() - parent.indents()
+        match self.kind {
+            Kind::Blank => 0,
+            // the content of the code block that is opened in the nested lines
+            Kind::CodeBlock => 1,
+            _ => self.indents() - parent.indents(),
+        }
+    }
+
+    // Re-parse the line as a row of the table, every cell is parsed into inline tokens and
+    // begins with a cell mark token.
+    fn parse_table_row(&mut self) {
+        let cells = Self::split_table_row(self.text())
+            .unwrap_or_else(|| vec![self.text().trim().to_string()]);
+
+        let mut buff = vec![];
+        for c in cells {
+            buff.push(Token::new("|".to_string(), TokenKind::TableCellMark));
+            buff.append(&mut Lexer::new(&c).split_inline_only());
+        }
+        self.buff = buff;
+        self.kind = Kind::Table;
+    }
+
+    // Split a row of the table into cells by the unescaped pipes('|'), the leading and trailing
+    // pipes are optional. Return None if there is no unescaped pipe in the row.
+    fn split_table_row(s: &str) -> Option<Vec<String>> {
+        let s = s.trim();
+        let mut cells = vec![];
+        let mut cell = String::new();
+        let mut found = false;
+
+        let mut iter = s.chars().peekable();
+        while let Some(c) = iter.next() {
+            match c {
+                '\\' if iter.peek() == Some(&'|') => {
+                    // the escaped pipe is a part of the cell
+                    cell.push('|');
+                    iter.next();
+                }
+                '|' => {
+                    found = true;
+                    cells.push(cell.trim().to_string());
+                    cell.clear();
+                }
+                _ => cell.push(c),
+            }
+        }
+        cells.push(cell.trim().to_string());
+
+        if s.starts_with('|') {
+            cells.remove(0);
+        }
+        if s.ends_with('|') && !s.ends_with("\\|") {
+            cells.pop();
         }
+        if !found || cells.is_empty() {
+            return None;
+        }
+        Some(cells)
     }
 
     fn first_token(&self) -> &Token {
@@ -607,8 +1753,26 @@ impl Line {
         {
             let gl = t.as_generic_link();
             let (tag, location, title) = (gl.tag(), gl.location(), gl.title());
+            // the first definition takes precedence if the tags are duplicated
             if !tag.is_empty() {
-                tags.insert(tag.to_string(), (location.to_string(), title.to_string()));
+                tags.entry(utils::normalize_label(tag))
+                    .or_insert_with(|| (location.to_string(), title.to_string()));
+            }
+        }
+    }
+
+    fn pick_footnotes(&self, footnotes: &mut HashMap<String, String>) {
+        for t in self
+            .all()
+            .iter()
+            .filter(|t| t.kind() == TokenKind::FootnoteDef)
+        {
+            let gl = t.as_generic_link();
+            let (label, text) = (gl.tag(), gl.name());
+            if !label.is_empty() {
+                footnotes
+                    .entry(label.to_string())
+                    .or_insert_with(|| text.to_string());
             }
         }
     }
@@ -619,39 +1783,169 @@ impl Line {
         l
     }
 
-    pub(crate) fn enter_nested_blocks(&self, generator: &impl Generate) -> String {
+    // The paragraphs are wrapped in "<p>" if the 'loose' is true, see 'is_loose_list'
+    pub(crate) fn enter_nested_blocks(&self, generator: &impl Generate, loose: bool) -> String {
         self.nested_blocks
             .iter()
+            // the paragraph following the list item is a part of the item
+            .skip(usize::from(self.continued_block().is_some()))
             .filter(|b| {
                 b.kind() != Kind::Meta__
                     && b.kind() != Kind::ListNesting__
                     && b.kind() != Kind::Blank
-                    && b.kind() != Kind::Title
-                    && b.kind() != Kind::Dividing
-                    && b.kind() != Kind::CodeBlockMark
-                    && b.kind() != Kind::CodeBlock
             })
             .map(|b| match b.kind() {
                 Kind::Title => generator.render_title(b.first()),
-                Kind::PlainText => generator.render_plain_text(b.contains()),
+                Kind::PlainText if loose => generator.render_plain_text(b.contains()),
+                Kind::PlainText => generator.render_tight_text(b.contains()),
                 Kind::Dividing => generator.render_dividing(b.first()),
                 Kind::CodeBlock => generator.render_code(b.contains()),
                 Kind::UnorderedList => generator.render_unordered_list(b.contains()),
                 Kind::Blank => generator.render_blank(b.contains()),
-                Kind::Quote => {
-                    let s = b
-                        .quote_ast
-                        .as_ref()
-                        .map(|a| a.generate_content(generator))
-                        .unwrap_or_else(|| "".to_string());
-                    generator.render_quote(&s)
-                }
+                Kind::Quote => b.generate_quote(generator),
+                Kind::Container => b.generate_container(generator),
+                Kind::Columns => b.generate_columns(generator),
                 Kind::OrderedList => generator.render_ordered_list(b.contains()),
+                Kind::DefinitionList => generator.render_definition_list(b.contains()),
+                Kind::Table => generator.render_table(b.contains()),
+                Kind::MathBlock => generator.render_math(b.contains()),
+                Kind::HtmlBlock => generator.render_raw_html(b.contains()),
                 _ => "".to_string(),
             })
             .join("\n")
     }
 
+    // Determine whether the list is loose, the items are separated by blank lines or an item
+    // contains the blocks separated by blank lines, .e.g "- a\n\n- b" or "- a\n\n  b". The
+    // description of definition list is loose if it follows a blank line, .e.g "term\n\n: text".
+    pub(crate) fn is_loose_list(ls: &[SharedLine]) -> bool {
+        // the blank lines are nested in the item only if they are followed by the list content
+        ls.iter().any(|l| {
+            l.borrow()
+                .nested_blocks
+                .iter()
+                .any(|b| b.kind() == Kind::Blank)
+        }) || ls
+            .iter()
+            .tuple_windows()
+            .any(|(a, b)| a.borrow().kind == Kind::Blank && b.borrow().kind == Kind::Description)
+    }
+
+    // Determine whether the line is a term of the definition list, .e.g "term" in "term\n: text"
+    pub(crate) fn is_term(&self) -> bool {
+        self.kind == Kind::PlainText
+    }
+
+    // Determine whether the line is a description of the definition list
+    pub(crate) fn is_description(&self) -> bool {
+        self.kind == Kind::Description
+    }
+
+    // Determine whether the line begins a description of the definition list, it begins with ':'
+    // and a white space, .e.g ": the description of term"
+    fn begins_description(&self) -> bool {
+        let s = self.text().trim_start();
+        self.kind == Kind::PlainText
+            && self.indent_width() < 4
+            && s.strip_prefix(':')
+                .is_some_and(|r| r.starts_with([' ', '\t']) && !r.trim().is_empty())
+    }
+
+    // Get the inline content of the line, the leading whitespaces and the mark of list are
+    // excluded, .e.g "item" in "- item"
+    fn inline_text(&self) -> &str {
+        let text = self.text.trim_start();
+        match self.kind {
+            Kind::UnorderedList | Kind::OrderedList => {
+                // the mark is followed by a whitespace
+                let rest = &text[self.mark_token().len()..];
+                rest.strip_prefix([' ', '\t']).unwrap_or(rest)
+            }
+            Kind::Description => text[1..].trim_start_matches([' ', '\t']),
+            _ => text,
+        }
+    }
+
+    // Determine whether the content of the list item begins a block rather than a paragraph,
+    // .e.g the quote of "- > quote" or the nested list of "- - item". The bare mark is a text,
+    // .e.g "- #".
+    fn leads_block(&self) -> bool {
+        let mut l = Line::new(0, self.inline_text().to_string());
+        if l.pre_parse() != Kind::PlainText {
+            return true;
+        }
+        l.strong_parse();
+        l.kind != Kind::PlainText
+            && l.kind != Kind::Blank
+            && l.all().iter().any(|t| t.kind() == TokenKind::Text)
+    }
+
+    // Get the paragraph that continues the text of list item, .e.g "- item\n  continued"
+    fn continued_block(&self) -> Option<&Block> {
+        // the text of the description is a part of its content already
+        if self.kind == Kind::Description {
+            return None;
+        }
+        // the nested lines follow the item, so the first line of the content is next to the item
+        self.nested_blocks
+            .first()
+            .filter(|b| b.kind() == Kind::PlainText && b.first().borrow().num == 1)
+    }
+
+    // Get the width of the indentation of the item content, the nested lines are indented at
+    // least this width, .e.g 2 of "- item" and 3 of "1. item"
+    fn content_indent(&self) -> usize {
+        if self.kind == Kind::Description {
+            // the description begins with ':' and a white space
+            return self.indent_width() + 2;
+        }
+        let mark = self.mark_token().value();
+        // the checkbox of todo list is a part of the content
+        let mark = mark.split_whitespace().next().unwrap_or(mark);
+        self.indent_width() + mark.chars().count() + 1
+    }
+
+    // Split the inline content of the paragraph as a whole, so the inline elements can span the
+    // lines, .e.g "[long\nlink](url)". The definitions of ref link and footnote are skipped.
+    pub(crate) fn paragraph_tokens(ls: &[SharedLine]) -> Vec<Token> {
+        let text: String = ls
+            .iter()
+            .map(|l| l.borrow())
+            .filter(|l| !l.is_definition())
+            .map(|l| l.inline_text().to_string())
+            .collect();
+        if text.is_empty() {
+            return vec![];
+        }
+        Lexer::new(&text).split_inline_only()
+    }
+
+    // Get the tokens of the list item, the marks are followed by the inline tokens. The text may
+    // continue on the following lines.
+    pub(crate) fn list_item_tokens(&self) -> Vec<Token> {
+        // the item of one line has been split already, .e.g the lines of toc
+        let Some(b) = self.continued_block() else {
+            return self.all().clone();
+        };
+        let mut text = self.inline_text().to_string();
+        b.contains()
+            .iter()
+            .for_each(|l| text.push_str(l.borrow().inline_text()));
+
+        let mut tokens: Vec<Token> = self
+            .all()
+            .iter()
+            .take_while(|t| {
+                t.kind() == TokenKind::WhiteSpace
+                    || t.kind() == TokenKind::UnorderedMark
+                    || t.kind() == TokenKind::OrderedMark
+            })
+            .cloned()
+            .collect();
+        tokens.append(&mut Lexer::new(&text).split_inline_only());
+        tokens
+    }
+
     // Get the mark token in the Line, the mark token may be the first or second
     pub(crate) fn mark_token(&self) -> &Token {
         let first = self.first_token();
@@ -663,11 +1957,6 @@ impl Line {
         }
     }
 
-    // Get the Nth Token in the Line
-    pub(crate) fn get(&self, at: usize) -> Option<&Token> {
-        self.buff.get(at)
-    }
-
     // Get all tokens in the Line
     pub(crate) fn all(&self) -> &Vec<Token> {
         &self.buff
@@ -678,7 +1967,89 @@ impl Line {
         &self.text
     }
 
-    // create a anchor name and id for the line
+    // Determine whether the list item belongs to the same list as the 'item', the items must use
+    // the same bullet character or delimiter, .e.g "-" and "*" begin two lists, so do "1." and "1)".
+    fn is_same_list(&self, item: &Line) -> bool {
+        self.kind == item.kind && self.list_delimiter() == item.list_delimiter()
+    }
+
+    // Get the bullet character of the unordered list or the delimiter of the ordered list
+    fn list_delimiter(&self) -> Option<char> {
+        let mark = self.mark_token().value();
+        match self.kind {
+            Kind::UnorderedList => mark.chars().next(),
+            Kind::OrderedList => mark.chars().last(),
+            _ => None,
+        }
+    }
+
+    // Get the number of the ordered list item, .e.g 3 of "3. item"
+    pub(crate) fn list_start(&self) -> Option<u32> {
+        if self.kind != Kind::OrderedList {
+            return None;
+        }
+        let mark = self.mark_token().value();
+        mark[..mark.len() - 1].parse().ok()
+    }
+
+    // Determine whether the line only contains the definitions of ref link or footnote
+    pub(crate) fn is_definition(&self) -> bool {
+        self.kind == Kind::PlainText
+            && self.all().iter().all(|t| {
+                t.kind() == TokenKind::RefLinkDef
+                    || t.kind() == TokenKind::FootnoteDef
+                    || t.kind() == TokenKind::WhiteSpace
+            })
+    }
+
+    // Get the cells of the table row, every cell contains its inline tokens
+    pub(crate) fn table_cells(&self) -> Vec<&[Token]> {
+        self.all()
+            .split(|t| t.kind() == TokenKind::TableCellMark)
+            .skip(1)
+            .collect()
+    }
+
+    // Get the alignments of the table columns from the delimiter row, the value is one of
+    // "left", "center", "right" or "" (not specified)
+    pub(crate) fn table_aligns(&self) -> Vec<&'static str> {
+        self.table_cells()
+            .iter()
+            .map(|c| {
+                let v = c.first().map(|t| t.value()).unwrap_or("");
+                match (v.starts_with(':'), v.ends_with(':')) {
+                    (true, true) => "center",
+                    (true, false) => "left",
+                    (false, true) => "right",
+                    (false, false) => "",
+                }
+            })
+            .collect()
+    }
+
+    // Get the attributes at the end of the title, .e.g [("id", "intro")] of "# Intro {#intro}"
+    pub(crate) fn attributes(&self) -> &[(String, String)] {
+        &self.attrs
+    }
+
+    // Get the custom id in the attributes of the title, .e.g "intro" of "# Intro {#intro}"
+    fn custom_id(&self) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == "id")
+            .map(|(_, id)| id.as_str())
+    }
+
+    // Determine whether the title has the class in the attributes, .e.g "no-toc" of
+    // "## Title {.no-toc}"
+    fn has_class(&self, class: &str) -> bool {
+        self.attrs
+            .iter()
+            .any(|(k, v)| k == "class" && v.split_whitespace().any(|c| c == class))
+    }
+
+    // Get the anchor id and name of the title, the id is unique in the document. The name is the
+    // raw text of the title, it's escaped when the name is rendered, .e.g the link in the toc.
     pub(crate) fn anchor(&self) -> (String, String) {
         if self.kind != Kind::Title {
             panic!("Only title line can create anchor");
@@ -687,14 +2058,34 @@ impl Line {
             .all()
             .iter()
             .filter(|t| t.kind() != TokenKind::WhiteSpace && t.kind() != TokenKind::TitleMark)
-            .map(|t| t.html_escaped_value())
+            .map(|t| match t.kind() {
+                TokenKind::SoftBreak => " ".to_string(),
+                _ => t.value().to_string(),
+            })
             .collect();
 
-        let name = ss.join("");
-        (
-            format!("{}-{}", &name.to_lowercase().replace(' ', "-"), self.num),
-            name,
-        )
+        (self.id.clone(), ss.join(""))
+    }
+
+    // Get the plain text of the tokens without the marks, .e.g "Intro code" of "# Intro `code`".
+    // The text of the link is its name and the image is ignored.
+    fn plain_text(tokens: &[Token]) -> String {
+        tokens
+            .iter()
+            .map(|t| match t.kind() {
+                TokenKind::Text | TokenKind::Math => t.value().to_string(),
+                TokenKind::WhiteSpace | TokenKind::SoftBreak | TokenKind::LineBreak => {
+                    " ".to_string()
+                }
+                TokenKind::Link | TokenKind::RefLink => {
+                    Self::plain_text(&t.as_generic_link().name_to_tokens())
+                }
+                TokenKind::QuickLink => {
+                    t.value().trim_matches(|c| c == '<' || c == '>').to_string()
+                }
+                _ => String::new(),
+            })
+            .collect()
     }
 }
 
@@ -705,24 +2096,43 @@ mod tests {
     struct MockGenerator {}
     impl Generate for MockGenerator {
         fn render_unordered_list(&self, ls: &[SharedLine]) -> String {
-            let list: Vec<String> = ls
+            format!("<ul>{}</ul>", render_items(ls))
+        }
+
+        fn render_ordered_list(&self, ls: &[SharedLine]) -> String {
+            format!("<ol>{}</ol>", render_items(ls))
+        }
+
+        fn render_plain_text(&self, ls: &[SharedLine]) -> String {
+            ls.iter()
+                .map(|l| l.borrow().text().trim().to_string())
+                .join(" ")
+        }
+
+        fn render_columns(&self, _attrs: &[(String, String)], columns: &[(u32, String)]) -> String {
+            columns
                 .iter()
-                .map(|l| {
-                    let leader = l.borrow().text().trim().to_string();
-                    let nesting = l.borrow().enter_nested_blocks(&MockGenerator {});
-                    if !nesting.is_empty() {
-                        leader + nesting.as_str()
-                    } else {
-                        leader
-                    }
-                })
-                .map(|s| format!("<li>{}</li>", s))
-                .collect();
-            format!("<ul>{}</ul>", list.join(""))
+                .map(|(r, s)| format!("<div flex={}>{}</div>", r, s))
+                .join("")
         }
     }
 
-    fn exec_document_cases(doc: &Vec<SharedLine>) -> Vec<(Kind, usize, usize, usize)> {
+    fn render_items(ls: &[SharedLine]) -> String {
+        ls.iter()
+            .map(|l| {
+                let leader = l.borrow().text().trim().to_string();
+                let nesting = l.borrow().enter_nested_blocks(&MockGenerator {}, false);
+                if !nesting.is_empty() {
+                    leader + nesting.as_str()
+                } else {
+                    leader
+                }
+            })
+            .map(|s| format!("<li>{}</li>", s))
+            .join("")
+    }
+
+    fn exec_document_cases(doc: &[SharedLine]) -> Vec<(Kind, usize, usize, usize)> {
         doc.iter()
             .skip(1)
             .map(|x| {
@@ -732,14 +2142,14 @@ mod tests {
             .collect()
     }
 
-    fn exec_blocks_cases(blocks: &Vec<Block>) -> Vec<(Kind, usize, Option<usize>)> {
+    fn exec_blocks_cases(blocks: &[Block]) -> Vec<(Kind, usize, Option<usize>)> {
         blocks
             .iter()
             .map(|x| {
                 (
                     x.kind(),
                     x.contains.len(),
-                    x.quote_ast
+                    x.sub_ast
                         .as_ref()
                         .map(|a| Some(a.count_lines()))
                         .unwrap_or(None),
@@ -769,21 +2179,88 @@ mod tests {
             (Kind::CodeBlock, 3, 0, 0),
             (Kind::CodeBlock, 4, 0, 0),
             (Kind::CodeBlockMark, 5, 0, 0),
-            (Kind::CodeBlockMark, 6, 0, 0),
-            (Kind::PlainText, 7, 0, 0),
-            (Kind::PlainText, 8, 0, 0),
-            (Kind::PlainText, 9, 0, 0),
+            (Kind::CodeBlock, 6, 0, 0),
+            (Kind::CodeBlock, 7, 0, 0),
+            (Kind::CodeBlock, 8, 0, 0),
+            (Kind::CodeBlock, 9, 0, 0),
         ];
         assert_eq!(exec_document_cases(&ast.document), document);
 
-        // (block kind, line count in block, line count of quote ast)
+        // (block kind, line count in block, line count of sub ast)
         let blocks = vec![
             (Kind::PlainText, 1, None),
             (Kind::CodeBlock, 4, None),
-            (Kind::CodeBlockMark, 1, None),
-            (Kind::PlainText, 3, None),
+            (Kind::CodeBlock, 4, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        assert_eq!(
+            ast.content_blocks()[2].contains()[0].borrow().code_text(),
+            "```\n"
+        );
+    }
+
+    #[test]
+    fn test_code_block_fence() {
+        let md = r#"~~~ rust  linenos
+```
+~~
+~~~
+text
+    not code
+
+    let a = 1;
+
+    let b = 2;
+
+  ````
+  ```
+    x
+````
+```
+unclosed
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (line kind, line number, nested line count, nested block count)
+        let document = vec![
+            (Kind::CodeBlockMark, 1, 0, 0),
+            (Kind::CodeBlock, 2, 0, 0),
+            (Kind::CodeBlock, 3, 0, 0),
+            (Kind::CodeBlockMark, 4, 0, 0),
+            (Kind::PlainText, 5, 0, 0),
+            (Kind::PlainText, 6, 0, 0),
+            (Kind::Blank, 7, 0, 0),
+            (Kind::CodeBlock, 8, 0, 0),
+            (Kind::CodeBlock, 9, 0, 0),
+            (Kind::CodeBlock, 10, 0, 0),
+            (Kind::Blank, 11, 0, 0),
+            (Kind::CodeBlockMark, 12, 0, 0),
+            (Kind::CodeBlock, 13, 0, 0),
+            (Kind::CodeBlock, 14, 0, 0),
+            (Kind::CodeBlockMark, 15, 0, 0),
+            (Kind::CodeBlockMark, 16, 0, 0),
+            (Kind::CodeBlock, 17, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::CodeBlock, 4, None),
+            (Kind::PlainText, 2, None),
+            (Kind::Blank, 1, None),
+            (Kind::CodeBlock, 3, None),
+            (Kind::Blank, 1, None),
+            (Kind::CodeBlock, 4, None),
+            (Kind::CodeBlock, 2, None),
         ];
         assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let b = &ast.content_blocks();
+        assert_eq!(b[0].first().borrow().code_info(), "rust  linenos");
+        assert_eq!(b[3].contains()[0].borrow().code_text(), "let a = 1;\n");
+        assert_eq!(b[5].contains()[1].borrow().code_text(), "```\n");
+        assert_eq!(b[5].contains()[2].borrow().code_text(), "  x\n");
     }
 
     #[test]
@@ -811,7 +2288,7 @@ mod tests {
             ];
             assert_eq!(exec_document_cases(&ast.document), document);
 
-            // (block kind, line count in block, line count of quote ast)
+            // (block kind, line count in block, line count of sub ast)
             let blocks = vec![
                 (Kind::Title, 1, None),
                 (Kind::Quote, 3, Some(3)),
@@ -820,12 +2297,306 @@ mod tests {
             ];
             assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
         }
-    }
+        {
+            let md = r#">a
+lazy
+> > nested
+> back
+
+> # title
+text
+- > in list
+  > more
+"#;
 
-    #[test]
-    fn test_nested_list() {
-        let md = r#"## 无序列表
-- 列表项 1
+            let mut ast = Ast::new();
+            ast.parse_string(md).unwrap();
+
+            // (block kind, line count in block, line count of sub ast)
+            let blocks = vec![
+                (Kind::Quote, 4, Some(4)),
+                (Kind::Blank, 1, None),
+                (Kind::Quote, 1, Some(1)),
+                (Kind::PlainText, 1, None),
+                (Kind::UnorderedList, 1, None),
+            ];
+            assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+            // the quote is nested in the quote, and the lazy line continues the nested paragraph
+            let quote = ast.content_blocks()[0].sub_ast.as_ref().unwrap();
+            let blocks = vec![(Kind::PlainText, 2, None), (Kind::Quote, 2, Some(2))];
+            assert_eq!(exec_blocks_cases(quote.content_blocks()), blocks);
+
+            let item = ast.content_blocks()[4].first().borrow();
+            let blocks = vec![(Kind::Quote, 2, Some(2))];
+            assert_eq!(exec_blocks_cases(&item.nested_blocks), blocks);
+        }
+        {
+            let md = r#"> - a
+> - b
+>
+> ```rust
+> > not a quote
+> ```
+> | a | b |
+> |---|---|
+> | 1 | 2 |
+"#;
+
+            let mut ast = Ast::new();
+            ast.parse_string(md).unwrap();
+
+            // (block kind, line count in block, line count of sub ast)
+            let blocks = vec![(Kind::Quote, 9, Some(9))];
+            assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+            let quote = ast.content_blocks()[0].sub_ast.as_ref().unwrap();
+            let blocks = vec![
+                (Kind::UnorderedList, 2, None),
+                (Kind::Blank, 1, None),
+                (Kind::CodeBlock, 3, None),
+                (Kind::Table, 3, None),
+            ];
+            assert_eq!(exec_blocks_cases(quote.content_blocks()), blocks);
+        }
+    }
+
+    #[test]
+    fn test_quote_definitions() {
+        let md = r#"[^2]: top
+
+> [link]: https://example.com "example"
+> [^1]: first
+> > [nested]: https://example.org
+
+- > [^2]: second
+  > [^3]: third
+
+[link]: https://example.net
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // the definitions in the quotes are available in the whole document, and the first
+        // definition in the order of the document takes precedence
+        assert_eq!(ast.ref_link_tags().len(), 2);
+        assert_eq!(
+            ast.ref_link_tags().get("link"),
+            Some(&("https://example.com".to_string(), "example".to_string()))
+        );
+        assert_eq!(
+            ast.ref_link_tags().get("nested"),
+            Some(&("https://example.org".to_string(), "".to_string()))
+        );
+        assert_eq!(ast.footnotes().len(), 3);
+        assert_eq!(ast.footnotes().get("1"), Some(&"first".to_string()));
+        assert_eq!(ast.footnotes().get("2"), Some(&"top".to_string()));
+        assert_eq!(ast.footnotes().get("3"), Some(&"third".to_string()));
+    }
+
+    #[test]
+    fn test_callout() {
+        let md = r#"> [!NOTE]
+> useful information
+> more
+
+> [!warning] Be careful
+> text
+
+> [!UNKNOWN]
+> text
+
+> [!TIP]
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (callout, line count of sub ast)
+        let callouts: Vec<_> = ast
+            .content_blocks()
+            .iter()
+            .filter(|b| b.kind() == Kind::Quote)
+            .map(|b| {
+                let n = b.sub_ast.as_ref().unwrap().count_lines();
+                (b.callout.clone(), n)
+            })
+            .collect();
+        assert_eq!(
+            callouts,
+            vec![
+                (Some(("note".to_string(), "Note".to_string())), 2),
+                (Some(("warning".to_string(), "Be careful".to_string())), 1),
+                (None, 2),
+                (Some(("tip".to_string(), "Tip".to_string())), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_container_block() {
+        let md = r#"::: warning Be careful {#tip .wide}
+text
+```
+:::
+```
+:::
+::::: tabs
+::: tab
+left
+:::
+:::::
+text
+::: unclosed
+rest
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::Container, 6, Some(4)),
+            (Kind::Container, 5, Some(3)),
+            (Kind::PlainText, 1, None),
+            (Kind::Container, 2, Some(1)),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let containers: Vec<_> = ast
+            .content_blocks()
+            .iter()
+            .filter_map(|b| b.container.as_ref())
+            .map(|c| (c.name.as_str(), c.title.as_str(), c.attrs.clone()))
+            .collect();
+        let attrs = vec![
+            ("id".to_string(), "tip".to_string()),
+            ("class".to_string(), "wide".to_string()),
+        ];
+        assert_eq!(
+            containers,
+            vec![
+                ("warning", "Be careful", attrs),
+                ("tabs", "", vec![]),
+                ("unclosed", "", vec![]),
+            ]
+        );
+
+        // the inner container is closed by the shorter fence
+        let tabs = ast.content_blocks()[1].sub_ast.as_ref().unwrap();
+        let blocks = vec![(Kind::Container, 3, Some(1))];
+        assert_eq!(exec_blocks_cases(tabs.content_blocks()), blocks);
+    }
+
+    #[test]
+    fn test_columns() {
+        let md = r#"::: columns 1:2
+intro
+::: column
+left
+:::
+::: column
+right
+
+---
+:::
+:::
+---
+::: columns
+::: column 3
+only
+:::
+:::
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::Columns, 11, Some(9)),
+            (Kind::Dividing, 1, None),
+            (Kind::Columns, 5, Some(3)),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        // the dividing line in the column doesn't split the slices
+        let slices = ast.generate_slice(&MockGenerator {});
+        assert_eq!(
+            slices,
+            vec![
+                "<div flex=1>intro</div><div flex=2>left</div><div flex=1>right</div>",
+                "<div flex=3>only</div>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definition_list() {
+        let md = r#"Apple
+: A fruit.
+: A company.
+
+Orange
+Citrus
+: Another fruit
+  continued.
+
+  Second paragraph.
+para
+
+not a term
+
+: loose
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::DefinitionList, 7, None),
+            (Kind::PlainText, 1, None),
+            (Kind::Blank, 1, None),
+            (Kind::DefinitionList, 3, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        // (line kind, line number, nested line count, nested block count)
+        let document = vec![
+            (Kind::PlainText, 1, 0, 0),
+            (Kind::Description, 2, 0, 1),
+            (Kind::Description, 3, 0, 1),
+            (Kind::Blank, 4, 0, 0),
+            (Kind::PlainText, 5, 0, 0),
+            (Kind::PlainText, 6, 0, 0),
+            (Kind::Description, 7, 3, 3),
+            (Kind::PlainText, 8, 0, 0),
+            (Kind::Blank, 9, 0, 0),
+            (Kind::PlainText, 10, 0, 0),
+            (Kind::PlainText, 11, 0, 0),
+            (Kind::Blank, 12, 0, 0),
+            (Kind::PlainText, 13, 0, 0),
+            (Kind::Blank, 14, 0, 0),
+            (Kind::Description, 15, 0, 1),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        // the description has multi paragraphs or follows a blank line
+        assert!(Line::is_loose_list(ast.content_blocks()[0].contains()));
+        assert!(Line::is_loose_list(ast.content_blocks()[3].contains()));
+
+        let mut ast = Ast::new();
+        ast.parse_string("a\n: b\n: c\n\nd\n: e\n").unwrap();
+        let blocks = vec![(Kind::DefinitionList, 6, None)];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        assert!(!Line::is_loose_list(ast.content_blocks()[0].contains()));
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let md = r#"## 无序列表
+- 列表项 1
     嵌入文本 1
   - 嵌入项 1
   - 嵌入项 2
@@ -845,24 +2616,592 @@ mod tests {
         ];
         assert_eq!(exec_document_cases(&ast.document), document);
 
-        // (block kind, line count in block, line count of quote ast)
+        // (block kind, line count in block, line count of sub ast)
         let blocks = vec![(Kind::Title, 1, None), (Kind::UnorderedList, 3, None)];
         assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
     }
 
+    #[test]
+    fn test_loose_list() {
+        let md = r#"- a
+
+- b
+  - c
+  - d
+
+  para
+- e
+
+text
+
+1. x
+2. y
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let document = vec![
+            (Kind::UnorderedList, 1, 1, 1),
+            (Kind::Blank, 2, 0, 0),
+            (Kind::UnorderedList, 3, 4, 3),
+            (Kind::UnorderedList, 4, 0, 0),
+            (Kind::UnorderedList, 5, 0, 0),
+            (Kind::Blank, 6, 0, 0),
+            (Kind::PlainText, 7, 0, 0),
+            (Kind::UnorderedList, 8, 0, 0),
+            (Kind::Blank, 9, 0, 0),
+            (Kind::PlainText, 10, 0, 0),
+            (Kind::Blank, 11, 0, 0),
+            (Kind::OrderedList, 12, 0, 0),
+            (Kind::OrderedList, 13, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        // the blank lines inside the list don't break it
+        let blocks = vec![
+            (Kind::UnorderedList, 3, None),
+            (Kind::Blank, 1, None),
+            (Kind::PlainText, 1, None),
+            (Kind::Blank, 1, None),
+            (Kind::OrderedList, 2, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let blocks = ast.content_blocks();
+        assert!(Line::is_loose_list(blocks[0].contains()));
+        assert!(!Line::is_loose_list(blocks[4].contains()));
+
+        // the nested list of the item "b" is tight
+        let b = blocks[0].contains()[1].borrow();
+        assert!(!Line::is_loose_list(b.nested_blocks[0].contains()));
+    }
+
+    #[test]
+    fn test_ordered_list_start() {
+        let md = r#"5. five
+6. six
+1) a
+2) b
+
+text
+2020. is a year
+1. list
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // the list is broken if the delimiter is changed
+        let blocks = vec![
+            (Kind::OrderedList, 2, None),
+            (Kind::OrderedList, 2, None),
+            (Kind::Blank, 1, None),
+            (Kind::PlainText, 2, None),
+            (Kind::OrderedList, 1, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let starts: Vec<Option<u32>> = ast
+            .content_blocks()
+            .iter()
+            .map(|b| b.first().borrow().list_start())
+            .collect();
+        assert_eq!(starts, vec![Some(5), Some(1), None, None, Some(1)]);
+    }
+
+    #[test]
+    fn test_list_item_blocks() {
+        let md = r#"1. install
+   ```bash
+   # comment
+
+   ```
+2. configure
+   ## title
+   ---
+   text
+
+       code
+3. next
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let blocks = vec![(Kind::OrderedList, 3, None)];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let items = ast.content_blocks()[0].contains();
+        let blocks = vec![(Kind::CodeBlock, 4, None)];
+        assert_eq!(exec_blocks_cases(&items[0].borrow().nested_blocks), blocks);
+
+        // the indentation of the item is removed from the code
+        let item = items[0].borrow();
+        let code = item.nested_blocks[0].contains()[1].borrow();
+        assert_eq!(code.all()[0].value(), "# comment\n");
+
+        let blocks = vec![
+            (Kind::Title, 1, None),
+            (Kind::Dividing, 1, None),
+            (Kind::PlainText, 1, None),
+            (Kind::Blank, 1, None),
+            (Kind::CodeBlock, 1, None),
+        ];
+        assert_eq!(exec_blocks_cases(&items[1].borrow().nested_blocks), blocks);
+
+        // the title in the list item is not a part of toc
+        assert!(ast.establish_toc(&TocOptions::default()).is_empty());
+
+        // the content of the item that begins with "---" is not a front matter
+        let mut ast = Ast::new();
+        ast.parse_string("-\n  ---\n  a: b\n  ---\n  text\n")
+            .unwrap();
+        let items = ast.content_blocks()[0].contains();
+        let blocks = vec![
+            (Kind::Dividing, 1, None),
+            (Kind::Title, 2, None),
+            (Kind::PlainText, 1, None),
+        ];
+        assert_eq!(exec_blocks_cases(&items[0].borrow().nested_blocks), blocks);
+    }
+
+    #[test]
+    fn test_table() {
+        let md = r#"| a | b \| c | *d* |
+|:--|:-:|--:|
+| 1 | `x\|y` |
+text
+- list
+
+| not a table |
+| --- | --- |
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let document = vec![
+            (Kind::Table, 1, 0, 0),
+            (Kind::Table, 2, 0, 0),
+            (Kind::Table, 3, 0, 0),
+            (Kind::Table, 4, 0, 0),
+            (Kind::UnorderedList, 5, 0, 0),
+            (Kind::Blank, 6, 0, 0),
+            (Kind::PlainText, 7, 0, 0),
+            (Kind::PlainText, 8, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        let blocks = vec![
+            (Kind::Table, 4, None),
+            (Kind::UnorderedList, 1, None),
+            (Kind::Blank, 1, None),
+            (Kind::PlainText, 2, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let head = ast.document[1].borrow();
+        let cells: Vec<Vec<&str>> = head
+            .table_cells()
+            .iter()
+            .map(|c| c.iter().map(|t| t.value()).collect())
+            .collect();
+        assert_eq!(cells, vec![vec!["a"], vec!["b | c"], vec!["*", "d", "*"]]);
+        assert_eq!(
+            ast.document[2].borrow().table_aligns(),
+            vec!["left", "center", "right"]
+        );
+        assert_eq!(ast.document[3].borrow().table_cells().len(), 2);
+    }
+
+    #[test]
+    fn test_math_block() {
+        let md = r#"$$
+\frac{a}{b}
+$$
+$$ x^2 $$
+text $y$
+
+$$
+not closed
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (line kind, line number, nested line count, nested block count)
+        let document = vec![
+            (Kind::MathBlock, 1, 0, 0),
+            (Kind::MathBlock, 2, 0, 0),
+            (Kind::MathBlock, 3, 0, 0),
+            (Kind::MathBlock, 4, 0, 0),
+            (Kind::PlainText, 5, 0, 0),
+            (Kind::Blank, 6, 0, 0),
+            (Kind::PlainText, 7, 0, 0),
+            (Kind::PlainText, 8, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::MathBlock, 3, None),
+            (Kind::MathBlock, 1, None),
+            (Kind::PlainText, 1, None),
+            (Kind::Blank, 1, None),
+            (Kind::PlainText, 2, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        assert!(ast.has_math());
+    }
+
+    #[test]
+    fn test_html_block() {
+        let md = r#"<div>
+*text*
+
+</div>
+<!-- a
+b -->
+<custom>
+text
+<span>
+    <p>
+"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (line kind, line number, nested line count, nested block count)
+        let document = vec![
+            (Kind::HtmlBlock, 1, 0, 0),
+            (Kind::HtmlBlock, 2, 0, 0),
+            (Kind::Blank, 3, 0, 0),
+            (Kind::HtmlBlock, 4, 0, 0),
+            (Kind::HtmlBlock, 5, 0, 0),
+            (Kind::HtmlBlock, 6, 0, 0),
+            (Kind::HtmlBlock, 7, 0, 0),
+            (Kind::HtmlBlock, 8, 0, 0),
+            (Kind::HtmlBlock, 9, 0, 0),
+            (Kind::HtmlBlock, 10, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        // (block kind, line count in block, line count of sub ast)
+        let blocks = vec![
+            (Kind::HtmlBlock, 2, None),
+            (Kind::Blank, 1, None),
+            (Kind::HtmlBlock, 7, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        let md = "text\n<span>\n<div>\n";
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let blocks = vec![(Kind::PlainText, 2, None), (Kind::HtmlBlock, 1, None)];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+    }
+
+    #[test]
+    fn test_setext_title() {
+        let md = r#"Header1
+===
+text
+Header2  
+---
+
+---
+- list
+---"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let document = vec![
+            (Kind::Title, 1, 0, 0),
+            (Kind::PlainText, 2, 0, 0),
+            (Kind::Title, 3, 0, 0),
+            (Kind::PlainText, 4, 0, 0),
+            (Kind::Dividing, 5, 0, 0),
+            (Kind::Blank, 6, 0, 0),
+            (Kind::Dividing, 7, 0, 0),
+            (Kind::UnorderedList, 8, 0, 0),
+            (Kind::Dividing, 9, 0, 0),
+        ];
+        assert_eq!(exec_document_cases(&ast.document), document);
+
+        let blocks = vec![
+            (Kind::Title, 2, None),
+            // the whole paragraph above the underline is the title
+            (Kind::Title, 3, None),
+            (Kind::Blank, 1, None),
+            (Kind::Dividing, 1, None),
+            (Kind::UnorderedList, 1, None),
+            (Kind::Dividing, 1, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+        assert_eq!(ast.document[1].borrow().mark_token().len(), 1);
+        assert_eq!(ast.document[3].borrow().mark_token().len(), 2);
+        assert_eq!(ast.document[3].borrow().anchor().1, "text Header2");
+        assert!(ast
+            .generate_toc(&TocOptions::default(), &MockGenerator {})
+            .contains("<li>text Header2</li>"));
+
+        // the definitions of the ref links are not a part of the title
+        let md = "[a]: /url\nfoo\nbar\n===\n";
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let blocks = vec![(Kind::PlainText, 1, None), (Kind::Title, 3, None)];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        assert_eq!(ast.document[2].borrow().anchor().1, "foo bar");
+
+        // a single '-' is the underline rather than an empty list item
+        let md = "Foo\n-\n\n-\n";
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+        let blocks = vec![
+            (Kind::Title, 2, None),
+            (Kind::Blank, 1, None),
+            (Kind::UnorderedList, 1, None),
+        ];
+        assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        assert_eq!(ast.document[1].borrow().mark_token().len(), 2);
+        assert_eq!(ast.document[1].borrow().anchor().1, "Foo");
+    }
+
+    #[test]
+    fn test_title_attributes() {
+        let md = r#"# Intro {#intro .lead}
+## Setup *fast* {#setup data-x="a b"} ##
+## Plain
+### Braces {not attributes}
+Setext {.big}
+======
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        // (anchor, attributes)
+        let titles: Vec<_> = ast
+            .content_blocks()
+            .iter()
+            .filter(|b| b.kind() == Kind::Title)
+            .map(|b| {
+                let l = b.first().borrow();
+                (l.anchor(), l.attributes().to_vec())
+            })
+            .collect();
+        let attr = |k: &str, v: &str| (k.to_string(), v.to_string());
+        let anchor = |id: &str, name: &str| (id.to_string(), name.to_string());
+        assert_eq!(
+            titles,
+            vec![
+                (
+                    anchor("intro", "Intro"),
+                    vec![attr("id", "intro"), attr("class", "lead")]
+                ),
+                (
+                    anchor("setup", "Setup *fast*"),
+                    vec![attr("id", "setup"), attr("data-x", "a b")]
+                ),
+                (anchor("plain", "Plain"), vec![]),
+                (
+                    anchor("braces-not-attributes", "Braces {not attributes}"),
+                    vec![]
+                ),
+                (anchor("setext", "Setext"), vec![attr("class", "big")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_title_ids() {
+        let md = r#"# Intro & Usage
+## Intro & Usage
+## `code` and [link](https://example.com)
+## 中文 标题！
+## Intro & Usage {#intro--usage-2}
+> ## Intro & Usage
+
+## Intro & Usage
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let mut ids = vec![];
+        for b in ast.content_blocks() {
+            match b.kind() {
+                Kind::Title => ids.push(b.first().borrow().anchor().0),
+                Kind::Quote => ids.push(
+                    b.sub_ast.as_ref().unwrap().content_blocks()[0]
+                        .first()
+                        .borrow()
+                        .anchor()
+                        .0,
+                ),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            ids,
+            vec![
+                "intro--usage",
+                "intro--usage-1",
+                "code-and-link",
+                "中文-标题",
+                "intro--usage-2",
+                "intro--usage-3",
+                "intro--usage-4",
+            ]
+        );
+
+        // the link of the toc refers to the id of the title
+        let toc = ast.establish_toc(&TocOptions::default());
+        let toc = toc[0].first().borrow();
+        let link = toc.all().iter().find(|t| t.kind() == TokenKind::Link);
+        assert_eq!(link.unwrap().as_generic_link().location(), "#intro--usage");
+
+        // the custom id is reserved even if it follows the title with the same slug
+        let mut ast = Ast::new();
+        ast.parse_string("# Intro\n# Other {#intro}\n> # Intro\n")
+            .unwrap();
+        let ids: Vec<String> = ast.content_blocks()[..2]
+            .iter()
+            .map(|b| b.first().borrow().anchor().0)
+            .collect();
+        assert_eq!(ids, vec!["intro-1", "intro"]);
+        let quote = ast.content_blocks()[2].sub_ast.as_ref().unwrap();
+        assert_eq!(
+            quote.content_blocks()[0].first().borrow().anchor().0,
+            "intro-2"
+        );
+    }
+
     #[test]
     fn test_ref_link_tags() {
         let md = r#"## 链接
 [Example][link] <br>
-[link]: https://www.example.com "example""#;
+[link]: https://www.example.com "example"
+[LINK]: https://www.example.org
+[Foo  Bar]: https://www.example.net"#;
         let mut ast = Ast::new();
         ast.parse_string(md).unwrap();
 
-        assert_eq!(ast.ref_link_tags().len(), 1);
+        assert_eq!(ast.ref_link_tags().len(), 2);
         assert_eq!(
             ast.ref_link_tags().get("link"),
             Some(&("https://www.example.com".to_string(), "example".to_string()))
         );
+        assert_eq!(
+            ast.ref_link_tags().get("foo bar"),
+            Some(&("https://www.example.net".to_string(), "".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let md = r#"text[^1] and [^2]
+[^1]: first
+[^2]: second
+[^1]: duplicated"#;
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        assert_eq!(ast.footnotes().len(), 2);
+        assert_eq!(ast.footnotes().get("1"), Some(&"first".to_string()));
+        assert_eq!(ast.footnotes().get("2"), Some(&"second".to_string()));
+        assert!(!ast.document[1].borrow().is_definition());
+        assert!(ast.document[2].borrow().is_definition());
+    }
+
+    #[test]
+    fn test_front_matter() {
+        {
+            let md = r#"---
+title: Hello
+tags: [rust, markdown]
+---
+# header1"#;
+            let mut ast = Ast::new();
+            ast.parse_string(md).unwrap();
+
+            assert_eq!(ast.metadata().get("title"), Some(&"Hello".into()));
+            assert_eq!(
+                ast.metadata().get("tags"),
+                Some(&serde_json::json!(["rust", "markdown"]))
+            );
+            let blocks = vec![(Kind::Title, 1, None)];
+            assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+        }
+        {
+            let md = r#"+++
+title = "Hello"
+date = 2023-01-02
++++
+text"#;
+            let mut ast = Ast::new();
+            ast.parse_string(md).unwrap();
+
+            assert_eq!(ast.metadata().get("title"), Some(&"Hello".into()));
+            assert_eq!(ast.metadata().get("date"), Some(&"2023-01-02".into()));
+        }
+        {
+            // it's not a front matter if it's not closed or not a map
+            for md in ["---\ntitle: Hello\n", "---\ntext\n---\n"] {
+                let mut ast = Ast::new();
+                ast.parse_string(md).unwrap();
+
+                assert!(ast.metadata().is_empty());
+                assert_eq!(ast.document[1].borrow().kind, Kind::Dividing);
+            }
+        }
+        {
+            // the empty front matter is parsed as normal lines, .e.g the title between the
+            // dividing lines isn't a YAML comment
+            let mut ast = Ast::new();
+            ast.parse_string("---\n# Title\n---\ntext\n").unwrap();
+
+            assert!(ast.metadata().is_empty());
+            let blocks = vec![
+                (Kind::Dividing, 1, None),
+                (Kind::Title, 1, None),
+                (Kind::Dividing, 1, None),
+                (Kind::PlainText, 1, None),
+            ];
+            assert_eq!(exec_blocks_cases(ast.content_blocks()), blocks);
+
+            for md in ["---\n---\ntext\n", "+++\n# comment\n+++\ntext\n"] {
+                let mut ast = Ast::new();
+                ast.parse_string(md).unwrap();
+                assert!(ast.metadata().is_empty());
+                assert!(ast.document[1..]
+                    .iter()
+                    .all(|l| l.borrow().kind != Kind::Meta__));
+            }
+        }
+        {
+            // the lines of the invalid front matter are parsed with the following lines
+            for md in ["---\ntext\n```\n---\n```\n", "---\n```\n---\n```\n"] {
+                let mut ast = Ast::new();
+                ast.parse_string(md).unwrap();
+
+                let kinds: Vec<Kind> = ast.document[1..].iter().map(|l| l.borrow().kind).collect();
+                assert_eq!(kinds[kinds.len() - 2], Kind::CodeBlock);
+                assert_eq!(kinds[kinds.len() - 1], Kind::CodeBlockMark);
+            }
+        }
+        {
+            // the front matter is only at the beginning of the document
+            for md in [
+                "> ---\n> a: b\n> ---\n> text\n",
+                "::: tab\n---\na: b\n---\ntext\n:::\n",
+            ] {
+                let mut ast = Ast::new();
+                ast.parse_string(md).unwrap();
+
+                let sub = ast.content_blocks()[0].sub_ast.as_ref().unwrap();
+                assert!(sub.metadata().is_empty());
+                assert_eq!(sub.document[1].borrow().kind, Kind::Dividing);
+                assert_eq!(sub.document[4].borrow().text().trim(), "text");
+                assert_eq!(sub.document[4].borrow().kind, Kind::PlainText);
+            }
+        }
     }
 
     #[test]
@@ -896,7 +3235,80 @@ mod tests {
         let mut ast = Ast::new();
         ast.parse_string(md).unwrap();
 
-        let s = ast.generate_toc(&MockGenerator {});
+        let s = ast.generate_toc(&TocOptions::default(), &MockGenerator {});
         assert_eq!(s, dest);
     }
+
+    #[test]
+    fn test_toc_options() {
+        let md = r#"<!-- toc min=2 max=3 ordered numbering -->
+# Guide
+## Install
+### Linux
+#### Debian
+### macOS {.no-toc}
+## Usage
+### Serve
+"#;
+
+        let mut ast = Ast::new();
+        ast.parse_string(md).unwrap();
+
+        let dest = "<ol>\
+<li>1 Install\
+<ol>\
+<li>1.1 Linux</li>\
+</ol>\
+</li>\
+<li>2 Usage\
+<ol>\
+<li>2.1 Serve</li>\
+</ol>\
+</li>\
+</ol>";
+        let s = ast.generate(ast.content_blocks(), &MockGenerator {});
+        assert!(s.starts_with(dest));
+
+        // the titles are nested in the closest title of the higher level
+        let opts = TocOptions {
+            min_level: 3,
+            exclude: "none".to_string(),
+            ..Default::default()
+        };
+        let dest = "<ul>\
+<li>Linux\
+<ul>\
+<li>Debian</li>\
+</ul>\
+</li>\
+<li>macOS</li>\
+<li>Serve</li>\
+</ul>";
+        assert_eq!(ast.generate_toc(&opts, &MockGenerator {}), dest);
+
+        // the marker with the invalid options is a comment
+        for s in [
+            "<!-- toc max=7 -->",
+            "<!-- toc min=3 max=2 -->",
+            "<!-- toc is here -->",
+            "<!-- tocs -->",
+        ] {
+            assert_eq!(TocOptions::from_marker(s), None);
+        }
+        assert_eq!(
+            TocOptions::from_marker("<!--TOC exclude=skip-->"),
+            Some(TocOptions {
+                exclude: "skip".to_string(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            TocOptions::from_marker("<!-- toc min=3 max=3 -->"),
+            Some(TocOptions {
+                min_level: 3,
+                max_level: 3,
+                ..Default::default()
+            })
+        );
+    }
 }
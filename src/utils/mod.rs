@@ -1,6 +1,8 @@
 use email_address::EmailAddress;
 use lazy_static::lazy_static;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use regex::Regex;
+use std::collections::HashMap;
 use url::Url;
 
 pub(crate) mod cursor;
@@ -12,6 +14,47 @@ lazy_static! {
     static ref S_QUOTED_STRING_RE: Regex = Regex::new("^\'([^\'\\\\]|\\\\.)*\'$").unwrap();
 }
 
+// This regex is used to match a raw html tag at the beginning of a string, it includes the
+// open tag, closing tag, comment, processing instruction, declaration and CDATA section.
+lazy_static! {
+    static ref HTML_TAG_RE: Regex = Regex::new(concat!(
+        "^(?:",
+        r#"<[A-Za-z][A-Za-z0-9-]*(?:\s+[A-Za-z_:][A-Za-z0-9_.:-]*(?:\s*=\s*(?:[^"'=<>`\x00-\x20]+|'[^']*'|"[^"]*"))?)*\s*/?>"#,
+        r"|</[A-Za-z][A-Za-z0-9-]*\s*>",
+        r"|<!--.*?-->",
+        r"|<\?.*?\?>",
+        r"|<![A-Za-z][^>]*>",
+        r"|<!\[CDATA\[.*?\]\]>",
+        ")"
+    ))
+    .unwrap();
+}
+
+// The characters are percent-encoded in the url, the reserved characters of the url and '%' are
+// kept, so the url that is encoded already is not encoded again.
+const URL_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+// The schemes allowed in the safe mode, the url without scheme is relative and always allowed
+pub(crate) const SAFE_URL_SCHEMES: [&str; 6] = ["http", "https", "ftp", "ftps", "mailto", "tel"];
+
+// The data urls allowed for the images in the safe mode
+const SAFE_DATA_IMAGES: [&str; 4] = [
+    "data:image/png",
+    "data:image/gif",
+    "data:image/jpeg",
+    "data:image/webp",
+];
+
 pub fn is_quoted_string(s: &str) -> bool {
     D_QUOTED_STRING_RE.is_match(s) || S_QUOTED_STRING_RE.is_match(s)
 }
@@ -23,3 +66,272 @@ pub fn is_url(s: &str) -> bool {
 pub fn is_email(s: &str) -> bool {
     EmailAddress::is_valid(s)
 }
+
+// Match a raw html tag at the beginning of the string, return the matched tag
+pub fn match_html_tag(s: &str) -> Option<&str> {
+    HTML_TAG_RE.find(s).map(|m| m.as_str())
+}
+
+// Determine whether the url is safe to be output in the safe mode, the scheme must be in the
+// allowlist, and the data url is only allowed for the images.
+pub fn is_safe_url(url: &str, is_image: bool) -> bool {
+    // the browsers ignore the whitespaces and control characters in the scheme, .e.g "java\tscript:"
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    let scheme = match url.find([':', '/', '?', '#']) {
+        Some(ix) if url[ix..].starts_with(':') => &url[..ix],
+        _ => return true,
+    };
+    if scheme == "data" {
+        return is_image && SAFE_DATA_IMAGES.iter().any(|p| url.starts_with(p));
+    }
+    SAFE_URL_SCHEMES.contains(&scheme)
+}
+
+// Percent-encode the url and escape it to be used as the value of a html attribute
+pub fn escape_url(url: &str) -> String {
+    utf8_percent_encode(url, URL_ENCODE_SET)
+        .to_string()
+        .replace('&', "&amp;")
+        .replace('\'', "&#39;")
+}
+
+// Escape the text to be used as the value of a html attribute
+pub fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Normalize the label of the reference link, the labels are matched case-insensitively and the
+// consecutive whitespaces are treated as one space, .e.g "Foo  Bar" matches "foo bar"
+pub fn normalize_label(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// Split the attribute list in the braces at the end of the text, .e.g "Title {#id .class key=value}".
+// The '#' begins the id, the '.' begins a class and the value may be quoted, .e.g 'title="a b"'.
+// The classes are merged into one attribute. If the braces don't contain a valid attribute list,
+// the text is returned as it is with no attribute.
+pub fn split_attributes(s: &str) -> (&str, Vec<(String, String)>) {
+    let text = s.trim_end();
+    let list = match text
+        .strip_suffix('}')
+        .and_then(|t| t.rfind('{').map(|ix| (ix, t)))
+    {
+        Some((ix, t)) => (&t[..ix], &t[ix + 1..]),
+        None => return (s, vec![]),
+    };
+
+    let mut attrs: Vec<(String, String)> = vec![];
+    let mut classes: Vec<&str> = vec![];
+    for word in split_quoted_words(list.1) {
+        let (key, value) = if let Some(id) = word.strip_prefix('#') {
+            ("id", id)
+        } else if let Some(class) = word.strip_prefix('.') {
+            classes.push(class);
+            continue;
+        } else if let Some((k, v)) = word.split_once('=') {
+            (k, v.trim_matches(|c| c == '"' || c == '\''))
+        } else {
+            return (s, vec![]);
+        };
+        if !is_attribute_name(key) || value.is_empty() {
+            return (s, vec![]);
+        }
+        attrs.push((key.to_string(), value.to_string()));
+    }
+    if classes.iter().any(|c| c.is_empty()) || (attrs.is_empty() && classes.is_empty()) {
+        return (s, vec![]);
+    }
+    if !classes.is_empty() {
+        attrs.push(("class".to_string(), classes.join(" ")));
+    }
+    (list.0.trim_end(), attrs)
+}
+
+// Split the text by white space, but the white space in the quotes is kept
+fn split_quoted_words(s: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut quote: Option<char> = None;
+    let mut begin: Option<usize> = None;
+
+    for (ix, c) in s.char_indices() {
+        match quote {
+            Some(q) if q == c => quote = None,
+            Some(_) => {}
+            None if c.is_whitespace() => {
+                if let Some(b) = begin.take() {
+                    words.push(&s[b..ix]);
+                }
+            }
+            None => {
+                begin.get_or_insert(ix);
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+            }
+        }
+    }
+    if let Some(b) = begin {
+        words.push(&s[b..]);
+    }
+    words
+}
+
+// Determine whether the name is valid for a html attribute, .e.g "data-id"
+fn is_attribute_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.'))
+}
+
+// Convert the text of the title to a slug like GitHub, the letters are lowercase, the spaces are
+// replaced with '-' and the punctuation is removed, .e.g "Hello, World!" is "hello-world". The
+// letters and numbers of any language are kept, .e.g "中文 标题" is "中文-标题".
+pub fn slugify(s: &str) -> String {
+    s.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            _ if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Generate the unique slugs in a document, the duplicated slug is suffixed with a number,
+// .e.g "intro", "intro-1", "intro-2".
+#[derive(Debug, Default)]
+pub struct Slugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Get the unique slug of the text
+    pub fn slug(&mut self, s: &str) -> String {
+        let origin = slugify(s);
+        let mut slug = origin.clone();
+        while self.occurrences.contains_key(&slug) {
+            let n = self.occurrences.entry(origin.clone()).or_insert(0);
+            *n += 1;
+            slug = format!("{}-{}", origin, n);
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+
+    // Reserve the id that is specified by the user, so the generated slugs don't conflict with it
+    pub fn reserve(&mut self, id: &str) {
+        self.occurrences.entry(id.to_string()).or_insert(0);
+    }
+}
+
+// Determine whether the character is a CJK character or punctuation that is written without the
+// spaces between words. Hangul is excluded since Korean separates the words by spaces.
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{2E80}'..='\u{2FDF}' // radicals
+        | '\u{3000}'..='\u{303F}' // symbols and punctuation
+        | '\u{3040}'..='\u{30FF}' // hiragana and katakana
+        | '\u{3100}'..='\u{312F}' // bopomofo
+        | '\u{31F0}'..='\u{31FF}' // katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}' // unified ideographs extension a
+        | '\u{4E00}'..='\u{9FFF}' // unified ideographs
+        | '\u{F900}'..='\u{FAFF}' // compatibility ideographs
+        | '\u{FF00}'..='\u{FF60}' // fullwidth forms
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{20000}'..='\u{3134F}' // unified ideographs extension b and later
+    )
+}
+
+// Convert the toml value to the json value, the datetime is converted to a string
+pub fn toml_to_json(v: toml::Value) -> serde_json::Value {
+    match v {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => serde_json::Value::from(i),
+        toml::Value::Float(f) => serde_json::Value::from(f),
+        toml::Value::Boolean(b) => serde_json::Value::Bool(b),
+        toml::Value::Datetime(d) => serde_json::Value::String(d.to_string()),
+        toml::Value::Array(a) => {
+            serde_json::Value::Array(a.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(t) => {
+            serde_json::Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_safe_url() {
+        for url in [
+            "https://example.com",
+            "HTTP://example.com",
+            "mailto:a@example.com",
+            "tel:+123",
+            "/path/to:file",
+            "page.html?a=b:c",
+            "#intro",
+            "",
+        ] {
+            assert!(is_safe_url(url, false), "{}", url);
+        }
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "java\tscript:alert(1)",
+            " javascript:alert(1)",
+            "vbscript:msgbox(1)",
+            "file:///etc/passwd",
+            "data:text/html;base64,PHNjcmlwdD4=",
+            "data:image/png;base64,iVBORw0KGgo=",
+        ] {
+            assert!(!is_safe_url(url, false), "{}", url);
+        }
+
+        // the data url is only allowed for the images
+        assert!(is_safe_url("data:image/png;base64,iVBORw0KGgo=", true));
+        assert!(!is_safe_url("data:image/svg+xml;base64,PHN2Zz4=", true));
+    }
+
+    #[test]
+    fn test_escape_url() {
+        assert_eq!(
+            escape_url("https://a.com/b c?x=1&y='2'#<d>"),
+            "https://a.com/b%20c?x=1&amp;y=&#39;2&#39;#%3Cd%3E"
+        );
+        // the url encoded already is not encoded again
+        assert_eq!(escape_url("/a%20b"), "/a%20b");
+        assert_eq!(escape_url("/中文\""), "/%E4%B8%AD%E6%96%87%22");
+    }
+
+    #[test]
+    fn test_is_cjk() {
+        for c in ['中', '文', 'ひ', 'カ', '。', '，', '𠀀'] {
+            assert!(is_cjk(c), "{}", c);
+        }
+        // Hangul is separated by spaces like Latin
+        for c in ['a', 'Z', '1', ' ', '.', 'é', '한'] {
+            assert!(!is_cjk(c), "{}", c);
+        }
+    }
+}
@@ -32,49 +32,15 @@ where
         self.queue.len()
     }
 
-    // Remove the element from the top of the stack, if 'f' return true. Otherwise insert the
-    // argument 'e' at the top of the stack.
-    pub(crate) fn pop_or_push<F>(&mut self, e: T, f: F) -> Option<T>
+    // Remove the element from the top of the stack if 'f' return true, return whether it's removed
+    pub(crate) fn pop_if<F>(&mut self, f: F) -> bool
     where
         F: Fn(&T) -> bool,
     {
-        if let Some(e) = self.queue.last() {
-            if f(e) {
-                return self.pop();
-            }
+        if self.queue.last().is_some_and(f) {
+            return self.pop().is_some();
         }
-        self.push(e);
-        None
-    }
-
-    // Remove and return all elements of a range from 'f' being true to the top of the stack.
-    pub(crate) fn pop_range<F>(&mut self, f: F) -> Vec<T>
-    where
-        F: Fn(&T) -> bool,
-    {
-        let mut pops: Vec<T> = vec![];
-        let mut position: Option<usize> = None;
-
-        for (ix, e) in self.queue.iter().rev().enumerate() {
-            if f(e) {
-                position = Some(self.queue.len() - 1 - ix);
-                break;
-            }
-        }
-        if let Some(p) = position {
-            loop {
-                if let Some(pop) = self.pop() {
-                    pops.push(pop);
-                    if self.queue.len() == p {
-                        break;
-                    }
-                } else {
-                    unreachable!()
-                }
-            }
-            pops.reverse();
-        }
-        pops
+        false
     }
 
     pub(crate) fn all_mut(&mut self) -> &mut Vec<T> {